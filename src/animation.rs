@@ -1,73 +1,283 @@
 use std::collections::HashMap;
 
 use bevy::{
+    asset::LoadState,
     prelude::{
-        error, AddAsset, Added, App, AssetServer, Assets, Changed, Commands, Component, CoreSet,
-        Entity, FromWorld, Handle, IntoSystemConfig, Plugin, Query, Res, Resource, TextureAtlas,
-        TextureAtlasSprite, Time, Without, World,
+        error, AddAsset, Added, App, AssetEvent, AssetServer, Assets, Changed, Commands, Component,
+        CoreSet, Entity, EventReader, EventWriter, FromWorld, Handle, IntoSystemConfig, Local,
+        Plugin, Query, Res, Resource, TextureAtlas, TextureAtlasSprite, Time, Without, World,
     },
-    reflect::TypeUuid,
-    time::{Timer, TimerMode},
+    reflect::{FromReflect, Reflect, TypeUuid},
 };
-use bevy_rapier2d::prelude::Velocity;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    loader::AnimationLoader,
-    player::{Jump, Player},
+    animation_graph::{
+        run_state_machines, update_character_clips, AnimationGraph, AnimationGraphLoader,
+    },
+    characters::Characters,
+    loader::{AnimationLoader, AsepriteLoader},
+    player::Player,
 };
 pub struct PhoxAnimationPlugin;
 
 impl Plugin for PhoxAnimationPlugin {
     fn build(&self, app: &mut App) {
         app.add_system(animate_sprite)
-            .add_system(change_player_animation)
+            .add_system(flip_sprites)
+            .add_system(update_character_clips.before(run_state_machines))
+            .add_system(run_state_machines)
             .add_system(update_animation_components)
+            .add_system(reload_animations)
+            .add_system(report_missing_animations)
             .add_system(add_frame_time.in_base_set(CoreSet::Last))
+            .add_event::<AnimationFrameEvent>()
+            .add_event::<AnimationFinished>()
             .add_asset::<SpriteAnimation>()
             .add_asset_loader(AnimationLoader)
+            .add_asset::<AnimationGraph>()
+            .init_asset_loader::<AnimationGraphLoader>()
+            .init_resource::<AnimationTimeScale>()
+            .register_type::<AnimationSpeed>()
+            .register_type::<Facing>()
+            .register_type::<AnimationTimeScale>()
+            .init_resource::<Characters>()
             .init_resource::<Animations>()
-            .init_asset_loader::<AnimationLoader>();
+            .init_asset_loader::<AnimationLoader>()
+            .init_asset_loader::<AsepriteLoader>();
     }
 }
 
 #[derive(TypeUuid)]
 #[uuid = "5b68f25a-835d-45f2-855d-94613a2da2fd"]
 pub struct SpriteAnimation {
+    /// index of the first frame in `texture_atlas`
+    pub start: usize,
     pub len: usize,
     pub frame_time: f32,
+    /// per frame overrides of `frame_time`
+    pub frame_times: Vec<f32>,
+    pub mode: PlaybackMode,
+    pub events: Vec<FrameTag>,
     pub texture_atlas: Handle<TextureAtlas>,
 }
 
-#[derive(Component)]
-pub struct FrameTime(pub Timer);
+impl SpriteAnimation {
+    pub fn frame_time(&self, frame: usize) -> f32 {
+        self.frame_times
+            .get(frame)
+            .copied()
+            .unwrap_or(self.frame_time)
+    }
+}
+
+/// What a clip does when it reaches its last frame
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum PlaybackMode {
+    /// start again from the first frame
+    #[default]
+    Loop,
+    /// play through one time then go back to the first frame and stop
+    Once,
+    /// play forwards then backwards forever
+    PingPong,
+    /// play through one time and hold the last frame
+    Clamp,
+}
+
+/// Tags a frame of a clip, sent as an [`AnimationFrameEvent`] when the frame is reached
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FrameTag {
+    pub frame: usize,
+    pub name: String,
+}
+
+/// Sent when an entity reaches a frame tagged in its clip
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct AnimationFrameEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+/// Sent when a clip that is not looping reaches its end
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub animation: Handle<SpriteAnimation>,
+}
+
+#[derive(Component, Default)]
+pub struct FrameTime {
+    elapsed: f32,
+    frame: usize,
+    reverse: bool,
+    finished: bool,
+    started: bool,
+}
 
 #[allow(dead_code)]
 impl FrameTime {
-    fn tick(&mut self, delta: std::time::Duration) {
-        self.0.tick(delta);
+    /// advances the clip by `delta` seconds calling `on_frame` for every frame entered
+    fn tick(&mut self, delta: f32, animation: &SpriteAnimation, mut on_frame: impl FnMut(usize)) {
+        if self.finished {
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            on_frame(self.frame);
+        }
+        self.elapsed += delta;
+        loop {
+            let frame_time = animation.frame_time(self.frame);
+            if frame_time <= 0. || self.elapsed < frame_time {
+                break;
+            }
+            self.elapsed -= frame_time;
+            self.step(animation);
+            if self.finished {
+                self.elapsed = 0.;
+                break;
+            }
+            on_frame(self.frame);
+        }
+    }
+    fn step(&mut self, animation: &SpriteAnimation) {
+        let last = animation.len.saturating_sub(1);
+        match animation.mode {
+            PlaybackMode::Loop => {
+                self.frame = if self.frame >= last {
+                    0
+                } else {
+                    self.frame + 1
+                };
+            }
+            PlaybackMode::Once => {
+                if self.frame >= last {
+                    self.frame = 0;
+                    self.finished = true;
+                } else {
+                    self.frame += 1;
+                }
+            }
+            PlaybackMode::Clamp => {
+                if self.frame >= last {
+                    self.finished = true;
+                } else {
+                    self.frame += 1;
+                }
+            }
+            PlaybackMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if self.reverse && self.frame == 0 {
+                    self.reverse = false;
+                } else if !self.reverse && self.frame >= last {
+                    self.reverse = true;
+                }
+                if self.reverse {
+                    self.frame -= 1;
+                } else {
+                    self.frame += 1;
+                }
+            }
+        }
     }
-    fn finished(&self) -> bool {
-        self.0.finished()
+    fn reset(&mut self) {
+        *self = FrameTime::default();
     }
-    fn frames(&self) -> usize {
-        self.0.times_finished_this_tick() as usize
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// How fast the clip on an entity plays, 0 pauses it
+#[derive(Component, Reflect)]
+pub struct AnimationSpeed(pub f32);
+
+impl Default for AnimationSpeed {
+    fn default() -> Self {
+        AnimationSpeed(1.)
+    }
+}
+
+/// The way a sprite looks, the art faces right so `Left` flips it
+#[derive(Component, Reflect, FromReflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Facing {
+    #[default]
+    Right,
+    Left,
+}
+
+impl Facing {
+    /// 1 for right, -1 for left
+    pub fn sign(self) -> f32 {
+        match self {
+            Facing::Right => 1.,
+            Facing::Left => -1.,
+        }
+    }
+    pub fn opposite(self) -> Facing {
+        match self {
+            Facing::Right => Facing::Left,
+            Facing::Left => Facing::Right,
+        }
+    }
+}
+
+/// Scales every animation, set to 0 to pause them all
+#[derive(Resource, Reflect)]
+pub struct AnimationTimeScale(pub f32);
+
+impl Default for AnimationTimeScale {
+    fn default() -> Self {
+        AnimationTimeScale(1.)
     }
 }
 
 fn animate_sprite(
     mut entities: Query<(
+        Entity,
         &mut TextureAtlasSprite,
         &Handle<SpriteAnimation>,
         &mut FrameTime,
+        Option<&AnimationSpeed>,
     )>,
     animations: Res<Assets<SpriteAnimation>>,
     time: Res<Time>,
+    time_scale: Res<AnimationTimeScale>,
+    mut frame_events: EventWriter<AnimationFrameEvent>,
+    mut finished_events: EventWriter<AnimationFinished>,
 ) {
-    for (mut sprite, animation, mut frame_time) in entities.iter_mut() {
-        let Some(animation) = animations.get(animation) else {error!("Animation Not Loaded"); continue;};
-        frame_time.tick(time.delta());
-        sprite.index += frame_time.frames();
-        sprite.index %= animation.len;
+    for (entity, mut sprite, handle, mut frame_time, speed) in entities.iter_mut() {
+        // missing clips are listed once by `report_missing_animations`
+        let Some(animation) = animations.get(handle) else {continue;};
+        let speed = speed.map_or(1., |speed| speed.0) * time_scale.0;
+        let delta = time.delta_seconds() * speed.max(0.);
+        let was_finished = frame_time.finished;
+        frame_time.tick(delta, animation, |frame| {
+            for tag in animation.events.iter().filter(|tag| tag.frame == frame) {
+                frame_events.send(AnimationFrameEvent {
+                    entity,
+                    name: tag.name.clone(),
+                });
+            }
+        });
+        if frame_time.finished && !was_finished {
+            finished_events.send(AnimationFinished {
+                entity,
+                animation: handle.clone(),
+            });
+        }
+        let index = animation.start + frame_time.frame;
+        if sprite.index != index {
+            sprite.index = index;
+        }
     }
 }
 
@@ -75,102 +285,24 @@ fn animate_sprite(
 pub struct Animations {
     animations: HashMap<Animation, Handle<SpriteAnimation>>,
     atlas: HashMap<Animation, Handle<TextureAtlas>>,
+    character_graph: Handle<AnimationGraph>,
 }
 
 impl FromWorld for Animations {
     fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
         let mut map = Animations {
             animations: HashMap::new(),
             atlas: HashMap::new(),
+            character_graph: asset_server.load("Animations/Character.graph.ron"),
         };
-        let asset_server = world.resource::<AssetServer>();
-        // Mask Dude
-        map.add_animation(
-            Animation::MaskIdle,
-            asset_server.load("Animations/Mask.san.ron"),
-        );
-        map.add_animation(
-            Animation::MaskFall,
-            asset_server.load("Animations/Mask.san.ron#Fall"),
-        );
-        map.add_animation(
-            Animation::MaskJump,
-            asset_server.load("Animations/Mask.san.ron#Jump"),
-        );
-        map.add_animation(
-            Animation::MaskDoubleJump,
-            asset_server.load("Animations/Mask.san.ron#DoubleJump"),
-        );
-        map.add_animation(
-            Animation::MaskRun,
-            asset_server.load("Animations/Mask.san.ron#Run"),
-        );
-
-        //Pink Man
-        map.add_animation(
-            Animation::PinkIdle,
-            asset_server.load("Animations/Pink.san.ron"),
-        );
-        map.add_animation(
-            Animation::PinkFall,
-            asset_server.load("Animations/Pink.san.ron#Fall"),
-        );
-        map.add_animation(
-            Animation::PinkJump,
-            asset_server.load("Animations/Pink.san.ron#Jump"),
-        );
-        map.add_animation(
-            Animation::PinkDoubleJump,
-            asset_server.load("Animations/Pink.san.ron#DoubleJump"),
-        );
-        map.add_animation(
-            Animation::PinkRun,
-            asset_server.load("Animations/Pink.san.ron#Run"),
-        );
-
-        //Ninja Frog
-        map.add_animation(
-            Animation::NinjaIdle,
-            asset_server.load("Animations/Ninja.san.ron"),
-        );
-        map.add_animation(
-            Animation::NinjaFall,
-            asset_server.load("Animations/Ninja.san.ron#Fall"),
-        );
-        map.add_animation(
-            Animation::NinjaJump,
-            asset_server.load("Animations/Ninja.san.ron#Jump"),
-        );
-        map.add_animation(
-            Animation::NinjaDoubleJump,
-            asset_server.load("Animations/Ninja.san.ron#DoubleJump"),
-        );
-        map.add_animation(
-            Animation::NinjaRun,
-            asset_server.load("Animations/Ninja.san.ron#Run"),
-        );
-
-        //Virtual Guy
-        map.add_animation(
-            Animation::GuyIdle,
-            asset_server.load("Animations/Guy.san.ron"),
-        );
-        map.add_animation(
-            Animation::GuyFall,
-            asset_server.load("Animations/Guy.san.ron#Fall"),
-        );
-        map.add_animation(
-            Animation::GuyJump,
-            asset_server.load("Animations/Guy.san.ron#Jump"),
-        );
-        map.add_animation(
-            Animation::GuyDoubleJump,
-            asset_server.load("Animations/Guy.san.ron#DoubleJump"),
-        );
-        map.add_animation(
-            Animation::GuyRun,
-            asset_server.load("Animations/Guy.san.ron#Run"),
-        );
+        let characters = world.resource::<Characters>();
+        for (player, character) in characters.iter() {
+            for state in CharacterAnimation::ALL {
+                let Some(path) = character.animation_path(state) else {error!("{} has no {:?} animation", character.name, state); continue;};
+                map.add_animation(Animation::Character(player, state), asset_server.load(path));
+            }
+        }
 
         // Collectables
         map.add_animation(
@@ -205,99 +337,93 @@ impl Animations {
     pub fn add_atlas(&mut self, id: Animation, handle: Handle<TextureAtlas>) {
         self.atlas.insert(id, handle);
     }
+    pub fn character_graph(&self) -> Handle<AnimationGraph> {
+        self.character_graph.clone()
+    }
+}
+
+/// logs every entry of `Animations` that did not resolve once loading is done
+fn report_missing_animations(
+    animations: Res<Animations>,
+    asset_server: Res<AssetServer>,
+    sprite_animations: Res<Assets<SpriteAnimation>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut done: Local<bool>,
+) {
+    if *done {
+        return;
+    }
+    let mut missing = Vec::new();
+    for (id, handle) in animations.animations.iter() {
+        match asset_server.get_load_state(handle) {
+            LoadState::Loading | LoadState::NotLoaded => return,
+            LoadState::Loaded if sprite_animations.get(handle).is_some() => {}
+            state => missing.push((*id, asset_server.get_handle_path(handle), state)),
+        }
+    }
+    for (id, handle) in animations.atlas.iter() {
+        match asset_server.get_load_state(handle) {
+            LoadState::Loading | LoadState::NotLoaded => return,
+            LoadState::Loaded if atlases.get(handle).is_some() => {}
+            state => missing.push((*id, asset_server.get_handle_path(handle), state)),
+        }
+    }
+    *done = true;
+    for (id, path, state) in missing {
+        match state {
+            LoadState::Loaded => error!("Animation {:?}: {:?} has no such label", id, path),
+            _ => error!("Animation {:?}: {:?} failed to load", id, path),
+        }
+    }
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub enum Animation {
-    MaskRun,
-    MaskIdle,
-    MaskJump,
-    MaskDoubleJump,
-    MaskFall,
+    Character(Player, CharacterAnimation),
     Strawberry,
     Bananas,
-    NinjaRun,
-    NinjaIdle,
-    NinjaJump,
-    NinjaDoubleJump,
-    NinjaFall,
-    PinkRun,
-    PinkIdle,
-    PinkJump,
-    PinkDoubleJump,
-    PinkFall,
-    GuyRun,
-    GuyIdle,
-    GuyJump,
-    GuyDoubleJump,
-    GuyFall,
     Terrain,
 }
 
-fn change_player_animation(
-    mut player: Query<(
-        &Player,
-        &mut Handle<SpriteAnimation>,
-        &mut TextureAtlasSprite,
-        &Jump,
-        &Velocity,
-    )>,
-    animations: Res<Animations>,
-) {
-    for (player, mut animation, mut sprite, jump, velocity) in &mut player {
-        if velocity.linvel.x < -0.1 {
-            sprite.flip_x = true;
-        } else if velocity.linvel.x > 0.1 {
-            sprite.flip_x = false;
-        }
+/// The states every character in `characters.ron` has a clip for
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+pub enum CharacterAnimation {
+    Idle,
+    Run,
+    Jump,
+    DoubleJump,
+    Fall,
+    /// sliding down a wall, the characters use their "Fall" sheet for it
+    /// as the "Wall Jump" sheets of the Pixel Adventure pack are not in `assets` yet
+    WallSlide,
+}
 
-        //Jumping if jump
-        let set = if velocity.linvel.y > 0.01 {
-            if jump.0 {
-                Animation::MaskJump
-            } else {
-                Animation::MaskDoubleJump
-            }
-        //Falling if no on ground
-        } else if velocity.linvel.y < -0.01 {
-            Animation::MaskFall
-        // if any move keys pressed set run sprite
-        } else if velocity.linvel.x != 0.0 {
-            Animation::MaskRun
-        } else {
-            Animation::MaskIdle
-        };
+impl CharacterAnimation {
+    pub const ALL: [CharacterAnimation; 6] = [
+        CharacterAnimation::Idle,
+        CharacterAnimation::Run,
+        CharacterAnimation::Jump,
+        CharacterAnimation::DoubleJump,
+        CharacterAnimation::Fall,
+        CharacterAnimation::WallSlide,
+    ];
 
-        let set = match player {
-            Player::Mask => set,
-            Player::Ninja => match set {
-                Animation::MaskRun => Animation::NinjaRun,
-                Animation::MaskIdle => Animation::NinjaIdle,
-                Animation::MaskJump => Animation::NinjaJump,
-                Animation::MaskDoubleJump => Animation::NinjaDoubleJump,
-                Animation::MaskFall => Animation::NinjaFall,
-                _ => unreachable!(),
-            },
-            Player::Pink => match set {
-                Animation::MaskRun => Animation::PinkRun,
-                Animation::MaskIdle => Animation::PinkIdle,
-                Animation::MaskJump => Animation::PinkJump,
-                Animation::MaskDoubleJump => Animation::PinkDoubleJump,
-                Animation::MaskFall => Animation::PinkFall,
-                _ => unreachable!(),
-            },
-            Player::Guy => match set {
-                Animation::MaskRun => Animation::GuyRun,
-                Animation::MaskIdle => Animation::GuyIdle,
-                Animation::MaskJump => Animation::GuyJump,
-                Animation::MaskDoubleJump => Animation::GuyDoubleJump,
-                Animation::MaskFall => Animation::GuyFall,
-                _ => unreachable!(),
-            },
-        };
+    /// the name of this state in animation graphs
+    pub fn name(self) -> &'static str {
+        match self {
+            CharacterAnimation::Idle => "Idle",
+            CharacterAnimation::Run => "Run",
+            CharacterAnimation::Jump => "Jump",
+            CharacterAnimation::DoubleJump => "DoubleJump",
+            CharacterAnimation::Fall => "Fall",
+            CharacterAnimation::WallSlide => "WallSlide",
+        }
+    }
+}
 
-        let Some(handle) = animations.get_animation(set) else {error!("No Animation {:?} Loaded", set); return;};
-        *animation = handle;
+fn flip_sprites(mut query: Query<(&mut TextureAtlasSprite, &Facing), Changed<Facing>>) {
+    for (mut sprite, facing) in &mut query {
+        sprite.flip_x = *facing == Facing::Left;
     }
 }
 
@@ -315,11 +441,34 @@ fn update_animation_components(
 ) {
     for (animation_handle, mut frame_time, mut atlas, mut sprite) in &mut query {
         let Some(animation) = animations.get(animation_handle) else {error!("animation not found"); continue;};
-        frame_time
-            .0
-            .set_duration(std::time::Duration::from_secs_f32(animation.frame_time));
+        frame_time.reset();
         *atlas = animation.texture_atlas.clone();
-        sprite.index %= animation.len;
+        sprite.index = animation.start;
+    }
+}
+
+/// restarts every entity playing a clip that was changed on disk
+fn reload_animations(
+    mut events: EventReader<AssetEvent<SpriteAnimation>>,
+    mut query: Query<(
+        &Handle<SpriteAnimation>,
+        &mut FrameTime,
+        &mut Handle<TextureAtlas>,
+        &mut TextureAtlasSprite,
+    )>,
+    animations: Res<Assets<SpriteAnimation>>,
+) {
+    for event in events.iter() {
+        let AssetEvent::Modified { handle } = event else {continue;};
+        let Some(animation) = animations.get(handle) else {continue;};
+        for (animation_handle, mut frame_time, mut atlas, mut sprite) in &mut query {
+            if animation_handle != handle {
+                continue;
+            }
+            frame_time.reset();
+            *atlas = animation.texture_atlas.clone();
+            sprite.index = animation.start;
+        }
     }
 }
 
@@ -338,11 +487,59 @@ fn add_frame_time(
 ) {
     for (entity, animation_handle, mut atlas, mut sprite) in &mut query {
         let Some(animation) = animations.get(animation_handle) else {error!("animation not found"); continue;};
-        commands.entity(entity).insert(FrameTime(Timer::new(
-            std::time::Duration::from_secs_f32(animation.frame_time),
-            TimerMode::Repeating,
-        )));
+        commands.entity(entity).insert(FrameTime::default());
         *atlas = animation.texture_atlas.clone();
-        sprite.index %= animation.len;
+        sprite.index = animation.start;
     }
 }
+
+#[test]
+fn playback_modes() {
+    let mut animation = SpriteAnimation {
+        start: 0,
+        len: 3,
+        frame_time: 1.,
+        frame_times: vec![0.5],
+        mode: PlaybackMode::Loop,
+        events: Vec::new(),
+        texture_atlas: Handle::default(),
+    };
+    let frames = |animation: &SpriteAnimation| {
+        let mut frame_time = FrameTime::default();
+        let mut frames = vec![frame_time.frame()];
+        for _ in 0..5 {
+            frame_time.tick(1., animation, |_| {});
+            frames.push(frame_time.frame());
+        }
+        (frames, frame_time.finished())
+    };
+    // frame 0 only lasts half a tick so every pass through it skips ahead
+    assert_eq!(frames(&animation), (vec![0, 1, 2, 1, 2, 0], false));
+    animation.frame_times.clear();
+    animation.mode = PlaybackMode::Once;
+    assert_eq!(frames(&animation), (vec![0, 1, 2, 0, 0, 0], true));
+    animation.mode = PlaybackMode::Clamp;
+    assert_eq!(frames(&animation), (vec![0, 1, 2, 2, 2, 2], true));
+    animation.mode = PlaybackMode::PingPong;
+    assert_eq!(frames(&animation), (vec![0, 1, 2, 1, 0, 1], false));
+}
+
+#[test]
+fn frame_events() {
+    let animation = SpriteAnimation {
+        start: 0,
+        len: 4,
+        frame_time: 1.,
+        frame_times: Vec::new(),
+        mode: PlaybackMode::Once,
+        events: Vec::new(),
+        texture_atlas: Handle::default(),
+    };
+    let mut frame_time = FrameTime::default();
+    let mut entered = Vec::new();
+    frame_time.tick(2.5, &animation, |frame| entered.push(frame));
+    assert_eq!(entered, vec![0, 1, 2]);
+    frame_time.tick(5., &animation, |frame| entered.push(frame));
+    assert_eq!(entered, vec![0, 1, 2, 3]);
+    assert!(frame_time.finished());
+}
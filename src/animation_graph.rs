@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, LoadedAsset},
+    prelude::{error, Assets, Changed, Component, Handle, Query, Res, Vec2},
+    reflect::TypeUuid,
+};
+use bevy_rapier2d::prelude::Velocity;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{Animation, Animations, CharacterAnimation, FrameTime, SpriteAnimation},
+    player::{AirJumps, Grounded, OnWall, Player},
+};
+
+/// States and the rules for moving between them, loaded from `.graph.ron` files
+#[derive(Debug, TypeUuid, Deserialize, Serialize)]
+#[uuid = "0c1f5a0e-6a51-4c0b-9d0c-3d3c2b6f4f7e"]
+pub struct AnimationGraph {
+    pub start: String,
+    /// checked in order, the first one that matches wins
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Transition {
+    /// states this transition can be taken from, empty means any state
+    #[serde(default)]
+    pub from: Vec<String>,
+    pub to: String,
+    /// all have to hold for the transition to be taken
+    #[serde(default)]
+    pub when: Vec<Condition>,
+    #[serde(default)]
+    pub blend: Blend,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum Condition {
+    VelocityX(Compare),
+    VelocityY(Compare),
+    Grounded(bool),
+    /// the `AirJumps` component, true once a jump has been made in the air
+    AirJumped(bool),
+    /// the `OnWall` component, true while sliding down a wall
+    OnWall(bool),
+    /// the current clip has played to its end
+    ClipFinished,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum Compare {
+    Above(f32),
+    Below(f32),
+    AbsAbove(f32),
+    AbsBelow(f32),
+}
+
+impl Compare {
+    fn test(self, value: f32) -> bool {
+        match self {
+            Compare::Above(v) => value > v,
+            Compare::Below(v) => value < v,
+            Compare::AbsAbove(v) => value.abs() > v,
+            Compare::AbsBelow(v) => value.abs() < v,
+        }
+    }
+}
+
+/// How to leave the current clip once a transition is picked
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Blend {
+    /// switch on the frame the transition is picked
+    #[default]
+    Immediate,
+    /// wait for the current clip to reach its last frame
+    FinishClip,
+}
+
+/// What the conditions of a graph are checked against
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GraphInput {
+    pub velocity: Option<Vec2>,
+    pub grounded: Option<bool>,
+    pub air_jumped: Option<bool>,
+    pub on_wall: Option<bool>,
+    pub clip_finished: bool,
+    /// the current clip is finished or showing its last frame
+    pub clip_at_end: bool,
+}
+
+impl Condition {
+    fn test(self, input: &GraphInput) -> bool {
+        match self {
+            Condition::VelocityX(cmp) => input.velocity.map_or(false, |v| cmp.test(v.x)),
+            Condition::VelocityY(cmp) => input.velocity.map_or(false, |v| cmp.test(v.y)),
+            Condition::Grounded(want) => input.grounded == Some(want),
+            Condition::AirJumped(want) => input.air_jumped == Some(want),
+            Condition::OnWall(want) => input.on_wall == Some(want),
+            Condition::ClipFinished => input.clip_finished,
+        }
+    }
+}
+
+impl AnimationGraph {
+    /// the state to be in next, `current` is `None` before the graph has started
+    pub fn next_state<'a>(&'a self, current: Option<&'a str>, input: &GraphInput) -> &'a str {
+        let Some(current) = current else {return &self.start;};
+        for transition in self.transitions.iter() {
+            if !transition.from.is_empty() && !transition.from.iter().any(|s| s == current) {
+                continue;
+            }
+            if !transition.when.iter().all(|c| c.test(input)) {
+                continue;
+            }
+            if transition.to != current
+                && transition.blend == Blend::FinishClip
+                && !input.clip_at_end
+            {
+                return current;
+            }
+            return &transition.to;
+        }
+        current
+    }
+}
+
+/// Drives the `Handle<SpriteAnimation>` of an entity from an [`AnimationGraph`]
+#[derive(Component)]
+pub struct AnimationStateMachine {
+    pub graph: Handle<AnimationGraph>,
+    pub state: Option<String>,
+}
+
+impl AnimationStateMachine {
+    pub fn new(graph: Handle<AnimationGraph>) -> AnimationStateMachine {
+        AnimationStateMachine { graph, state: None }
+    }
+}
+
+/// The clip to play for each state of the graph
+#[derive(Component, Default)]
+pub struct AnimationClips(pub HashMap<String, Handle<SpriteAnimation>>);
+
+pub fn update_character_clips(
+    mut query: Query<(&Player, &mut AnimationClips), Changed<Player>>,
+    animations: Res<Animations>,
+) {
+    for (player, mut clips) in &mut query {
+        clips.0.clear();
+        for state in CharacterAnimation::ALL {
+            if let Some(handle) = animations.get_animation(Animation::Character(*player, state)) {
+                clips.0.insert(state.name().to_string(), handle);
+            }
+        }
+    }
+}
+
+pub fn run_state_machines(
+    mut query: Query<(
+        &mut AnimationStateMachine,
+        &AnimationClips,
+        &mut Handle<SpriteAnimation>,
+        Option<&FrameTime>,
+        Option<&Velocity>,
+        Option<&Grounded>,
+        Option<&AirJumps>,
+        Option<&OnWall>,
+    )>,
+    graphs: Res<Assets<AnimationGraph>>,
+    sprite_animations: Res<Assets<SpriteAnimation>>,
+) {
+    for (mut machine, clips, mut animation, frame_time, velocity, grounded, air_jumps, on_wall) in
+        &mut query
+    {
+        let Some(graph) = graphs.get(&machine.graph) else {continue;};
+        let clip_len = sprite_animations.get(&animation).map_or(0, |a| a.len);
+        let input = GraphInput {
+            velocity: velocity.map(|v| v.linvel),
+            grounded: grounded.map(|g| g.0),
+            air_jumped: air_jumps.map(|a| a.0 > 0),
+            on_wall: on_wall.map(|w| w.0.is_some()),
+            clip_finished: frame_time.map_or(false, |t| t.finished()),
+            clip_at_end: frame_time.map_or(true, |t| t.finished() || t.frame() + 1 >= clip_len),
+        };
+        let next = graph.next_state(machine.state.as_deref(), &input);
+        if machine.state.as_deref() != Some(next) {
+            machine.state = Some(next.to_string());
+        }
+        let Some(state) = &machine.state else {continue;};
+        let Some(handle) = clips.0.get(state) else {error!("No clip for state {}", state); continue;};
+        // only touch the handle on a real change so the clip is not restarted every frame
+        if *animation != *handle {
+            *animation = handle.clone();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AnimationGraphLoader;
+
+impl AssetLoader for AnimationGraphLoader {
+    fn extensions(&self) -> &[&str] {
+        &["graph.ron"]
+    }
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let graph: AnimationGraph = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(graph));
+            Ok(())
+        })
+    }
+}
+
+#[test]
+fn character_graph() {
+    let graph: AnimationGraph =
+        ron::de::from_str(include_str!("../assets/Animations/Character.graph.ron"))
+            .expect("valid graph");
+    let input = |velocity: Vec2, air_jumped: bool| GraphInput {
+        velocity: Some(velocity),
+        grounded: Some(true),
+        air_jumped: Some(air_jumped),
+        ..Default::default()
+    };
+    assert_eq!(graph.next_state(None, &input(Vec2::ZERO, false)), "Idle");
+    let idle = Some("Idle");
+    assert_eq!(graph.next_state(idle, &input(Vec2::ZERO, false)), "Idle");
+    assert_eq!(graph.next_state(idle, &input(Vec2::X, false)), "Run");
+    assert_eq!(graph.next_state(idle, &input(Vec2::Y, false)), "Jump");
+    assert_eq!(graph.next_state(idle, &input(Vec2::Y, true)), "DoubleJump");
+    assert_eq!(graph.next_state(idle, &input(Vec2::NEG_Y, true)), "Fall");
+    let sliding = GraphInput {
+        on_wall: Some(true),
+        ..input(Vec2::NEG_Y, false)
+    };
+    assert_eq!(graph.next_state(idle, &sliding), "WallSlide");
+}
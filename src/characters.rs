@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::FileAssetIo,
+    prelude::{error, AssetServer, FromWorld, Resource, World},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{animation::CharacterAnimation, player::Player};
+
+const MANIFEST_PATH: &str = "characters.ron";
+
+/// One entry of `characters.ron`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Character {
+    pub name: String,
+    /// path to the `.san.ron` file holding this characters animations
+    pub animations: String,
+    /// label of each state in the animation file, `None` is the default asset
+    pub states: HashMap<CharacterAnimation, Option<String>>,
+    #[serde(default)]
+    pub abilities: Abilities,
+    #[serde(default)]
+    pub unlock: Unlock,
+}
+
+/// How a character gets added to the roster
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Unlock {
+    /// playable from the start
+    #[default]
+    Always,
+    /// reach this score in one run
+    Score(usize),
+    /// finish the level with this asset path, like `Levels/test.lvl.ron`
+    Level(String),
+}
+
+/// What a character can do beyond running and jumping
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Abilities {
+    /// slide down walls and jump off them
+    pub wall_jump: bool,
+    /// jumps that can be made before landing again, 1 is the double jump
+    pub air_jumps: usize,
+    /// fastest fall while holding jump, `None` can't glide
+    pub glide: Option<f32>,
+    /// dash in the air as well as on the ground, once per jump
+    pub air_dash: bool,
+}
+
+impl Default for Abilities {
+    fn default() -> Self {
+        Abilities {
+            wall_jump: false,
+            air_jumps: 1,
+            glide: None,
+            air_dash: false,
+        }
+    }
+}
+
+impl Character {
+    pub fn animation_path(&self, state: CharacterAnimation) -> Option<String> {
+        match self.states.get(&state)? {
+            Some(label) => Some(format!("{}#{}", self.animations, label)),
+            None => Some(self.animations.clone()),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Characters(Vec<Character>);
+
+impl FromWorld for Characters {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        let path = match asset_server.asset_io().downcast_ref::<FileAssetIo>() {
+            Some(io) => io.root_path().join(MANIFEST_PATH),
+            None => FileAssetIo::get_base_path()
+                .join("assets")
+                .join(MANIFEST_PATH),
+        };
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("Failed to read {:?}: {}", path, e);
+                return Characters(Vec::new());
+            }
+        };
+        match ron::de::from_bytes(&bytes) {
+            Ok(characters) => Characters(characters),
+            Err(e) => {
+                error!("Failed to parse {:?}: {}", path, e);
+                Characters(Vec::new())
+            }
+        }
+    }
+}
+
+impl Characters {
+    pub fn get(&self, player: Player) -> Option<&Character> {
+        self.0.get(player.0)
+    }
+    /// the abilities of `player`, the defaults if it is not in the manifest
+    pub fn abilities(&self, player: Player) -> Abilities {
+        self.get(player)
+            .map(|character| character.abilities)
+            .unwrap_or_default()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (Player, &Character)> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, character)| (Player(i), character))
+    }
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn next(&self, player: Player) -> Player {
+        if self.0.is_empty() {
+            return player;
+        }
+        Player((player.0 + 1) % self.0.len())
+    }
+    pub fn prev(&self, player: Player) -> Player {
+        if self.0.is_empty() {
+            return player;
+        }
+        Player((player.0 + self.0.len() - 1) % self.0.len())
+    }
+}
+
+#[test]
+fn manifest() {
+    let characters: Vec<Character> =
+        ron::de::from_str(include_str!("../assets/characters.ron")).expect("valid manifest");
+    assert!(!characters.is_empty());
+    assert!(characters.iter().any(|c| c.abilities.wall_jump));
+    for character in characters {
+        assert_eq!(
+            character.animation_path(CharacterAnimation::Idle),
+            Some(character.animations.clone())
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::{
+    prelude::{
+        App, Commands, DetectChanges, Entity, IntoSystemConfig, Plugin, Query, Res, Time,
+        Transform, With, Without,
+    },
+    reflect::Reflect,
+};
+use bevy_rapier2d::prelude::{
+    ActiveCollisionTypes, CharacterAutostep, CharacterLength, Collider, CollisionGroups, Group,
+    KinematicCharacterController, KinematicCharacterControllerOutput, QueryFilter,
+    QueryFilterFlags, RapierConfiguration, RapierContext, RigidBody, TimestepMode, Velocity,
+};
+use leafwing_input_manager::prelude::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    map::{platform_top, OneWayPlatform, ONE_WAY_GROUP},
+    movement::MovementConfig,
+    player::{Dash, DropThrough, Grounded, PlayerStages, RealPlayer},
+    user_input::PlayerInput,
+};
+
+pub struct ControllerPlugin;
+
+impl Plugin for ControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(sync_controller_mode.before(PlayerStages::Move))
+            .add_system(read_kinematic_output.before(PlayerStages::Move))
+            .add_system(drive_kinematic_controller.after(PlayerStages::Move));
+    }
+}
+
+/// How the player body is moved
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect, Deserialize, Serialize)]
+pub enum ControllerMode {
+    /// a dynamic rigid body pushed around by `Velocity`, with `auto_step` for ledges
+    #[default]
+    Dynamic,
+    /// rapier's `KinematicCharacterController`, `Velocity` is integrated by hand
+    Kinematic,
+}
+
+/// the time the next physics step will cover
+fn physics_dt(rapier_config: &RapierConfiguration, time: &Time) -> f32 {
+    match rapier_config.timestep_mode {
+        TimestepMode::Fixed { dt, .. } | TimestepMode::Interpolated { dt, .. } => dt,
+        TimestepMode::Variable {
+            max_dt, time_scale, ..
+        } => (time.delta_seconds() * time_scale).min(max_dt),
+    }
+}
+
+fn kinematic_controller(config: &MovementConfig) -> KinematicCharacterController {
+    KinematicCharacterController {
+        offset: CharacterLength::Absolute(0.1),
+        autostep: Some(CharacterAutostep {
+            max_height: CharacterLength::Absolute(config.step_height),
+            min_width: CharacterLength::Absolute(1.),
+            include_dynamic_bodies: false,
+        }),
+        max_slope_climb_angle: config.max_slope_angle.to_radians(),
+        min_slope_slide_angle: config.max_slope_angle.to_radians(),
+        snap_to_ground: Some(CharacterLength::Absolute(config.snap_to_ground)),
+        filter_flags: QueryFilterFlags::EXCLUDE_SENSORS,
+        ..Default::default()
+    }
+}
+
+/// the parts a real player swaps in for kinematic mode, rapier leaves out kinematic-fixed
+/// pairs by default so without them no sensor like a collectable or checkpoint is touched
+pub fn kinematic_body(
+    config: &MovementConfig,
+) -> (
+    RigidBody,
+    KinematicCharacterController,
+    ActiveCollisionTypes,
+) {
+    (
+        RigidBody::KinematicPositionBased,
+        kinematic_controller(config),
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+    )
+}
+
+/// swaps the body of every real player over when the mode in the config changes,
+/// and keeps the controller settings in step with the config
+fn sync_controller_mode(
+    mut commands: Commands,
+    dynamic: Query<Entity, (With<RealPlayer>, Without<KinematicCharacterController>)>,
+    kinematic: Query<Entity, (With<RealPlayer>, With<KinematicCharacterController>)>,
+    config: Res<MovementConfig>,
+) {
+    match config.controller {
+        ControllerMode::Dynamic => {
+            for entity in &kinematic {
+                commands
+                    .entity(entity)
+                    .remove::<(
+                        KinematicCharacterController,
+                        KinematicCharacterControllerOutput,
+                        ActiveCollisionTypes,
+                    )>()
+                    .insert(RigidBody::Dynamic);
+            }
+        }
+        ControllerMode::Kinematic => {
+            let changed = config.is_changed();
+            for entity in dynamic.iter().chain(kinematic.iter().filter(|_| changed)) {
+                commands.entity(entity).insert(kinematic_body(&config));
+            }
+        }
+    }
+}
+
+/// whether the body overlaps a one-way platform whose top is above its feet
+fn inside_platform(
+    rapier_context: &RapierContext,
+    entity: Entity,
+    pos: &Transform,
+    collider: &Collider,
+    platforms: &Query<&Transform, With<OneWayPlatform>>,
+) -> bool {
+    let Some(cuboid) = collider.as_cuboid() else {return false;};
+    let feet = pos.translation.y - cuboid.half_extents().y;
+    let mut below_top = false;
+    rapier_context.intersections_with_shape(
+        pos.translation.truncate(),
+        0.,
+        collider,
+        QueryFilter::only_fixed()
+            .groups(CollisionGroups::new(Group::ALL, ONE_WAY_GROUP))
+            .exclude_rigid_body(entity),
+        |platform| {
+            below_top = platforms
+                .get(platform)
+                .map_or(false, |transform| platform_top(transform) > feet);
+            !below_top
+        },
+    );
+    below_top
+}
+
+/// applies gravity and ground friction that rapier only does for dynamic bodies,
+/// then hands the movement to the controller
+fn drive_kinematic_controller(
+    mut query: Query<(
+        Entity,
+        &mut KinematicCharacterController,
+        &mut Velocity,
+        &Transform,
+        &Collider,
+        &Grounded,
+        &Dash,
+        Option<&DropThrough>,
+        &ActionState<PlayerInput>,
+    )>,
+    platforms: Query<&Transform, With<OneWayPlatform>>,
+    rapier_context: Res<RapierContext>,
+    config: Res<MovementConfig>,
+    rapier_config: Res<RapierConfiguration>,
+    time: Res<Time>,
+) {
+    let dt = physics_dt(&rapier_config, &time);
+    for (entity, mut controller, mut velocity, pos, collider, grounded, dash, drop, input) in
+        &mut query
+    {
+        if !dash.is_dashing() {
+            velocity.linvel.y += config.gravity * dt;
+            let steering = input.pressed(PlayerInput::Left) || input.pressed(PlayerInput::Right);
+            if grounded.0 && !steering {
+                let slow_down = config.acceleration.min(velocity.linvel.x.abs());
+                velocity.linvel.x -= slow_down * velocity.linvel.x.signum();
+            } else {
+                velocity.linvel.x *= (1. - config.linear_damping * dt).max(0.);
+            }
+        }
+        // solver hooks never see the controller, so it leaves out the one-way platforms itself
+        // while rising, dropping or already partly through one
+        let pass = velocity.linvel.y > 0.
+            || drop.map_or(false, |drop| drop.is_dropping())
+            || inside_platform(&rapier_context, entity, pos, collider, &platforms);
+        let filter_groups =
+            pass.then(|| CollisionGroups::new(Group::ALL, Group::ALL.difference(ONE_WAY_GROUP)));
+        if controller.filter_groups != filter_groups {
+            controller.filter_groups = filter_groups;
+        }
+        controller.translation = Some(velocity.linvel * dt);
+    }
+}
+
+/// stops the velocity going into whatever the controller ran into
+fn read_kinematic_output(mut query: Query<(&KinematicCharacterControllerOutput, &mut Velocity)>) {
+    for (output, mut velocity) in &mut query {
+        let desired = output.desired_translation;
+        let effective = output.effective_translation;
+        if output.grounded && velocity.linvel.y < 0. {
+            velocity.linvel.y = 0.;
+        }
+        if desired.y > 0. && effective.y < desired.y * 0.5 {
+            velocity.linvel.y = 0.;
+        }
+        if effective.x.abs() < desired.x.abs() * 0.5 {
+            velocity.linvel.x = 0.;
+        }
+    }
+}
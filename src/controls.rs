@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use bevy::{
+    asset::FileAssetIo,
+    prelude::{error, App, DetectChanges, Plugin, Query, Res, Resource},
+};
+use leafwing_input_manager::{
+    prelude::InputMap,
+    user_input::{InputKind, UserInput},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{RealPlayer, MAX_LOCAL_PLAYERS},
+    user_input::PlayerInput,
+};
+
+const CONTROLS_PATH: &str = "controls.ron";
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Controls::load())
+            .add_system(apply_controls);
+    }
+}
+
+/// The input map of every local player slot, kept in `controls.ron`
+#[derive(Resource, Deserialize, Serialize)]
+pub struct Controls(Vec<InputMap<PlayerInput>>);
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls((0..MAX_LOCAL_PLAYERS).map(PlayerInput::for_slot).collect())
+    }
+}
+
+impl Controls {
+    fn path() -> PathBuf {
+        FileAssetIo::get_base_path().join(CONTROLS_PATH)
+    }
+
+    pub fn load() -> Controls {
+        let path = Controls::path();
+        let Ok(bytes) = std::fs::read(&path) else {return Controls::default();};
+        match ron::de::from_bytes::<Controls>(&bytes) {
+            Ok(mut controls) => {
+                // an older file might not have all the slots
+                for slot in controls.0.len()..MAX_LOCAL_PLAYERS {
+                    controls.0.push(PlayerInput::for_slot(slot));
+                }
+                controls
+            }
+            Err(e) => {
+                error!("Failed to parse {:?}: {}", path, e);
+                Controls::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = Controls::path();
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(anyhow::Error::from)
+            .and_then(|text| std::fs::write(&path, text).map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            error!("Failed to save {:?}: {}", path, e);
+        }
+    }
+
+    pub fn for_slot(&self, slot: usize) -> InputMap<PlayerInput> {
+        self.0
+            .get(slot)
+            .cloned()
+            .unwrap_or_else(|| PlayerInput::for_slot(slot))
+    }
+
+    /// the bindings of `action` as text, like `Space, W | South`
+    pub fn describe(&self, slot: usize, action: PlayerInput) -> String {
+        let Some(map) = self.0.get(slot) else {return String::new();};
+        let (keys, pad): (Vec<_>, Vec<_>) = map
+            .get(action)
+            .iter()
+            .partition(|input| matches!(input, UserInput::Single(InputKind::Keyboard(_))));
+        let names = |inputs: Vec<&UserInput>| {
+            inputs
+                .into_iter()
+                .map(input_name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!("{} | {}", names(keys), names(pad))
+    }
+
+    /// replaces the bindings of `action` that are the same kind of input as `input`,
+    /// so a new key keeps the gamepad buttons and the other way round
+    pub fn rebind(&mut self, slot: usize, action: PlayerInput, input: InputKind) {
+        let Some(map) = self.0.get_mut(slot) else {return;};
+        let is_key = |kind: &InputKind| matches!(kind, InputKind::Keyboard(_));
+        let kept: Vec<UserInput> = map
+            .get(action.clone())
+            .iter()
+            .filter(|old| match old {
+                UserInput::Single(kind) => is_key(kind) != is_key(&input),
+                _ => true,
+            })
+            .cloned()
+            .collect();
+        map.clear_action(action.clone());
+        for old in kept {
+            map.insert(old, action.clone());
+        }
+        map.insert(input, action);
+    }
+
+    pub fn reset(&mut self, slot: usize) {
+        if let Some(map) = self.0.get_mut(slot) {
+            *map = PlayerInput::for_slot(slot);
+        }
+    }
+}
+
+fn input_name(input: &UserInput) -> String {
+    match input {
+        UserInput::Single(InputKind::Keyboard(key)) => format!("{:?}", key),
+        UserInput::Single(InputKind::GamepadButton(button)) => format!("{:?}", button),
+        UserInput::Single(InputKind::SingleAxis(axis)) => format!("{:?}", axis.axis_type),
+        other => format!("{:?}", other),
+    }
+}
+
+/// gives the real players their new bindings as soon as they are changed
+fn apply_controls(
+    controls: Res<Controls>,
+    mut players: Query<(&RealPlayer, &mut InputMap<PlayerInput>)>,
+) {
+    if !controls.is_changed() {
+        return;
+    }
+    for (&RealPlayer(slot), mut map) in &mut players {
+        *map = controls.for_slot(slot);
+    }
+}
+
+#[test]
+fn rebind() {
+    use bevy::prelude::{GamepadButtonType, KeyCode};
+    let mut controls = Controls::default();
+    controls.rebind(0, PlayerInput::Jump, InputKind::Keyboard(KeyCode::K));
+    assert_eq!(controls.describe(0, PlayerInput::Jump), "K | South");
+    controls.rebind(
+        0,
+        PlayerInput::Jump,
+        InputKind::GamepadButton(GamepadButtonType::East),
+    );
+    assert_eq!(controls.describe(0, PlayerInput::Jump), "K | East");
+    let text = ron::ser::to_string(&controls).expect("controls serialize");
+    let loaded: Controls = ron::de::from_str(&text).expect("controls deserialize");
+    assert_eq!(loaded.describe(0, PlayerInput::Jump), "K | East");
+}
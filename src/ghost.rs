@@ -1,9 +1,10 @@
+use std::collections::HashMap;
+
 use bevy::{
-    ecs::query::QuerySingleError,
     prelude::{
-        error, App, Commands, Component, CoreSet, DetectChangesMut, Entity, EventReader,
-        EventWriter, Handle, Input, IntoSystemConfig, KeyCode, Local, Name, ParamSet, Plugin,
-        Query, Res, ResMut, Resource, Transform, Vec3, With,
+        error, App, Commands, Component, CoreSet, Entity, EventReader, EventWriter, Handle, Input,
+        IntoSystemConfig, KeyCode, Local, Name, ParamSet, Plugin, Query, Res, ResMut, Resource,
+        Transform, Vec3, With,
     },
     sprite::{SpriteSheetBundle, TextureAtlasSprite},
     time::{Time, Timer, TimerMode},
@@ -15,20 +16,23 @@ use bevy_rapier2d::prelude::{
 use leafwing_input_manager::prelude::ActionState;
 
 use crate::{
-    animation::{Animation, Animations},
-    map::LoadedLevel,
-    player::{Grounded, GroundedCheck, Jump, Player, PlayerStages, RealPlayer},
+    animation::{Animation, AnimationSpeed, Animations, CharacterAnimation, Facing},
+    animation_graph::{AnimationClips, AnimationStateMachine},
+    lives::{LivesStages, PlayerDied},
+    map::ONE_WAY_GROUP,
+    movement::MovementConfig,
+    player::{
+        AirJumps, Dash, DropThrough, GroundContact, Grounded, Jump, JumpTimers, OnWall, Player,
+        PlayerStages, RealPlayer, SpawnPoint,
+    },
     user_input::PlayerInput,
-    Score,
 };
 
 pub struct GhostPlugin;
 
 impl Plugin for GhostPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<PlayerInputs>()
-            .init_resource::<SyncOffset>()
-            .insert_resource(PlayerFrame(0))
+        app.init_resource::<Trails>()
             .add_system(update_frame.in_base_set(CoreSet::First))
             .add_system(save_player_state.in_base_set(CoreSet::Last))
             .add_system(save_player_offset.in_base_set(CoreSet::Last))
@@ -37,32 +41,44 @@ impl Plugin for GhostPlugin {
             .add_system(test_ghost)
             .add_event::<GhostEvents>()
             .add_system(handle_ghost_event)
-            .add_system(kill_player)
+            .add_system(kill_player.in_set(LivesStages::Kill))
             .add_system(auto_ghost);
     }
 }
 
+/// Replays the trail of the real player in slot `owner`
 #[derive(Component)]
-pub struct Ghost(usize);
+pub struct Ghost {
+    owner: usize,
+    frame: usize,
+}
 
 const SYNCFRAME: usize = 10;
 
-#[derive(Resource)]
-struct PlayerFrame(usize);
+/// What a ghost copies from the player for one frame
+struct PlayerState {
+    velocity: Velocity,
+    jump: Jump,
+    player: Player,
+    facing: Facing,
+    on_wall: OnWall,
+    dash: Dash,
+    drop_through: DropThrough,
+}
 
-#[derive(Resource, Default)]
-struct PlayerInputs(Vec<(Velocity, Jump, Player)>);
+#[derive(Default)]
+struct PlayerInputs(Vec<PlayerState>);
 
 impl PlayerInputs {
-    fn add_input(&mut self, state: (Velocity, Jump, Player)) {
+    fn add_input(&mut self, state: PlayerState) {
         self.0.push(state);
     }
-    fn get_input(&self, frame: usize) -> Option<&(Velocity, Jump, Player)> {
+    fn get_input(&self, frame: usize) -> Option<&PlayerState> {
         self.0.get(frame)
     }
 }
 
-#[derive(Resource, Default)]
+#[derive(Default)]
 struct SyncOffset(Vec<Vec3>);
 
 impl SyncOffset {
@@ -74,99 +90,163 @@ impl SyncOffset {
     }
 }
 
-fn update_frame(mut frame: ResMut<PlayerFrame>, mut query: Query<&mut Ghost>) {
-    for mut frame in query.iter_mut() {
-        frame.0 += 1;
+/// Everything recorded of one real player since their trail was last cleared
+#[derive(Default)]
+struct Trail {
+    frame: usize,
+    inputs: PlayerInputs,
+    offsets: SyncOffset,
+}
+
+/// The trail of each real player, by their slot
+#[derive(Resource, Default)]
+struct Trails(HashMap<usize, Trail>);
+
+fn update_frame(mut trails: ResMut<Trails>, mut query: Query<&mut Ghost>) {
+    for mut ghost in query.iter_mut() {
+        ghost.frame += 1;
+    }
+    for trail in trails.0.values_mut() {
+        trail.frame += 1;
     }
-    frame.0 += 1;
 }
 
 fn save_player_state(
-    query: Query<(&Velocity, &Jump, &Player), With<RealPlayer>>,
-    mut inputs: ResMut<PlayerInputs>,
+    query: Query<(
+        &RealPlayer,
+        &Velocity,
+        &Jump,
+        &Player,
+        &Facing,
+        &OnWall,
+        &Dash,
+        &DropThrough,
+    )>,
+    mut trails: ResMut<Trails>,
 ) {
-    let player = query.single();
-    inputs.add_input((player.0.clone(), *player.1, *player.2));
+    for (&RealPlayer(slot), velocity, jump, player, facing, on_wall, dash, drop_through) in &query {
+        trails
+            .0
+            .entry(slot)
+            .or_default()
+            .inputs
+            .add_input(PlayerState {
+                velocity: velocity.clone(),
+                jump: *jump,
+                player: *player,
+                facing: *facing,
+                on_wall: *on_wall,
+                dash: *dash,
+                drop_through: *drop_through,
+            });
+    }
 }
 
-fn save_player_offset(
-    query: Query<&Transform, With<RealPlayer>>,
-    frame: Res<PlayerFrame>,
-    mut offsets: ResMut<SyncOffset>,
-) {
-    if frame.0 % SYNCFRAME == 0 {
-        let player = query.single();
-        offsets.add_offset(player.translation);
+fn save_player_offset(query: Query<(&RealPlayer, &Transform)>, mut trails: ResMut<Trails>) {
+    for (&RealPlayer(slot), transform) in &query {
+        let trail = trails.0.entry(slot).or_default();
+        if trail.frame % SYNCFRAME == 0 {
+            trail.offsets.add_offset(transform.translation);
+        }
     }
 }
 
 fn update_ghost(
-    mut ghosts: Query<(&mut Velocity, &mut Jump, &mut Player, &Ghost)>,
-    inputs: Res<PlayerInputs>,
+    mut ghosts: Query<(
+        &mut Velocity,
+        &mut Jump,
+        &mut Player,
+        &mut Facing,
+        &mut OnWall,
+        &mut Dash,
+        &mut DropThrough,
+        &Ghost,
+    )>,
+    trails: Res<Trails>,
 ) {
     use std::mem::size_of;
-    for (mut v, mut j, mut p, &Ghost(frame)) in &mut ghosts {
-        if frame % 600 == 0 {
+    for (mut v, mut j, mut p, mut f, mut w, mut d, mut t, ghost) in &mut ghosts {
+        let Some(trail) = trails.0.get(&ghost.owner) else {continue;};
+        if ghost.frame % 600 == 0 {
             println!(
                 "PlayerInputs = {}",
-                inputs.0.len() * size_of::<(Velocity, Jump)>()
+                trail.inputs.0.len() * size_of::<PlayerState>()
             );
         }
-        if let Some((new_v, new_j, new_p)) = inputs.get_input(frame) {
-            *v = new_v.clone();
-            *j = *new_j;
-            *p = *new_p;
+        if let Some(state) = trail.inputs.get_input(ghost.frame) {
+            *v = state.velocity.clone();
+            *j = state.jump;
+            *p = state.player;
+            if *f != state.facing {
+                *f = state.facing;
+            }
+            if *w != state.on_wall {
+                *w = state.on_wall;
+            }
+            *d = state.dash;
+            *t = state.drop_through;
         }
     }
 }
 
-fn test_ghost(input: Res<Input<KeyCode>>, mut events: EventWriter<GhostEvents>) {
-    if input.just_pressed(KeyCode::Escape) {
-        events.send(GhostEvents::SpawnGhost);
-    }
-    if input.just_pressed(KeyCode::F5) {
-        events.send(GhostEvents::ClearGhosts);
-        events.send(GhostEvents::ClearTrail);
+fn test_ghost(
+    input: Res<Input<KeyCode>>,
+    players: Query<&RealPlayer>,
+    mut events: EventWriter<GhostEvents>,
+) {
+    for &RealPlayer(slot) in &players {
+        if input.just_pressed(KeyCode::Escape) {
+            events.send(GhostEvents::SpawnGhost(slot));
+        }
+        if input.just_pressed(KeyCode::F5) {
+            events.send(GhostEvents::ClearGhosts(slot));
+            events.send(GhostEvents::ClearTrail(slot));
+        }
     }
 }
 
-fn drift_correct(mut query: Query<(&Ghost, &mut Transform)>, offsets: Res<SyncOffset>) {
+fn drift_correct(mut query: Query<(&Ghost, &mut Transform)>, trails: Res<Trails>) {
     use std::mem::size_of;
-    for (&Ghost(frame), mut transform) in &mut query {
+    for (ghost, mut transform) in &mut query {
+        let Some(trail) = trails.0.get(&ghost.owner) else {continue;};
+        let frame = ghost.frame;
         if frame % 600 == 0 {
-            println!("offsets = {}", offsets.0.len() * size_of::<Vec3>());
+            println!("offsets = {}", trail.offsets.0.len() * size_of::<Vec3>());
         }
         if frame % SYNCFRAME != 0 || frame == 0 {
             continue;
         }
-        let Some(offset) = offsets.get_offset((frame - 1) / SYNCFRAME) else {error!("No Sync for frame {}", frame); continue;};
+        let Some(offset) = trail.offsets.get_offset((frame - 1) / SYNCFRAME) else {error!("No Sync for frame {}", frame); continue;};
         transform.translation = *offset;
     }
 }
 
 fn handle_ghost_event(
     mut events: EventReader<GhostEvents>,
-    mut frame: ResMut<PlayerFrame>,
-    mut inputs: ResMut<PlayerInputs>,
-    mut offsets: ResMut<SyncOffset>,
+    mut trails: ResMut<Trails>,
     mut commands: Commands,
-    ghosts: Query<Entity, With<Ghost>>,
+    ghosts: Query<(Entity, &Ghost)>,
     animations: Res<Animations>,
+    config: Res<MovementConfig>,
 ) {
     for event in events.iter() {
         match event {
-            GhostEvents::ClearTrail => {
-                frame.0 = 1;
-                inputs.0.clear();
-                offsets.0.clear();
+            GhostEvents::ClearTrail(slot) => {
+                let trail = trails.0.entry(*slot).or_default();
+                trail.frame = 1;
+                trail.inputs.0.clear();
+                trail.offsets.0.clear();
             }
-            GhostEvents::ClearGhosts => {
-                for ghost in &ghosts {
-                    commands.entity(ghost).despawn();
+            GhostEvents::ClearGhosts(slot) => {
+                for (entity, ghost) in &ghosts {
+                    if ghost.owner == *slot {
+                        commands.entity(entity).despawn();
+                    }
                 }
             }
-            GhostEvents::SpawnGhost => {
-                let Some(handle) = animations.get_animation(Animation::MaskIdle) else {error!("Failed to find animation: Idle"); return;};
+            GhostEvents::SpawnGhost(slot) => {
+                let player = Player::default();
+                let Some(handle) = animations.get_animation(Animation::Character(player, CharacterAnimation::Idle)) else {error!("Failed to find animation: Idle"); return;};
                 commands.spawn((
                     (
                         SpriteSheetBundle {
@@ -177,60 +257,74 @@ fn handle_ghost_event(
                             },
                             ..Default::default()
                         },
-                        Player::Mask,
-                        handle,
+                        player,
+                        (
+                            handle,
+                            AnimationStateMachine::new(animations.character_graph()),
+                            AnimationClips::default(),
+                            AnimationSpeed::default(),
+                            Facing::default(),
+                        ),
                         Grounded(true),
-                        GroundedCheck::default(),
+                        GroundContact::default(),
                         ActionState::<PlayerInput>::default(),
-                        Jump(false),
+                        (
+                            Jump(false),
+                            AirJumps::default(),
+                            JumpTimers::default(),
+                            OnWall::default(),
+                            Dash::default(),
+                            DropThrough::default(),
+                        ),
                         RigidBody::Dynamic,
                         Velocity::default(),
                         Collider::cuboid(9., 16.),
                         LockedAxes::ROTATION_LOCKED_Z,
                         Friction {
-                            coefficient: 5.,
+                            coefficient: config.friction,
                             combine_rule: CoefficientCombineRule::Multiply,
                         },
                         Damping {
-                            linear_damping: 1.,
+                            linear_damping: config.linear_damping,
                             angular_damping: 1.,
                         },
                         Name::new("Ghost"),
-                        Ghost(0),
+                        Ghost {
+                            owner: *slot,
+                            frame: 0,
+                        },
                     ),
-                    CollisionGroups::new(Group::GROUP_2, Group::GROUP_1),
+                    CollisionGroups::new(Group::GROUP_2, Group::GROUP_1 | ONE_WAY_GROUP),
                 ));
             }
         }
     }
 }
 
+/// Each event is for the trail and ghosts of the real player in that slot
 pub enum GhostEvents {
-    ClearTrail,
-    ClearGhosts,
-    SpawnGhost,
+    ClearTrail(usize),
+    ClearGhosts(usize),
+    SpawnGhost(usize),
 }
 
 fn kill_player(
     rapier_context: Res<RapierContext>,
-    mut player: Query<(Entity, &mut Transform, &mut Velocity), With<RealPlayer>>,
+    players: Query<(Entity, &Dash), With<RealPlayer>>,
     ghosts: Query<Entity, With<Ghost>>,
-    mut events: EventWriter<GhostEvents>,
-    mut score: ResMut<Score>,
-    mut loaded_level: ResMut<LoadedLevel>,
+    mut deaths: EventWriter<PlayerDied>,
 ) {
-    let (player, mut pos, mut vel) = player.single_mut();
-    for ghost in &ghosts {
-        let Some(contact) = rapier_context.contact_pair(player, ghost) else { continue;};
-        if contact.has_any_active_contacts() {
-            println!("score = {}", score.0);
-            score.0 = 0;
-            events.send(GhostEvents::ClearGhosts);
-            events.send(GhostEvents::ClearTrail);
-            *vel = Velocity::zero();
-            *pos = Transform::IDENTITY;
-            loaded_level.set_changed();
-        };
+    for (player, dash) in &players {
+        if dash.is_immune() {
+            continue;
+        }
+        for ghost in &ghosts {
+            let Some(contact) = rapier_context.contact_pair(player, ghost) else { continue;};
+            if contact.has_any_active_contacts() {
+                deaths.send(PlayerDied(player));
+                break;
+            }
+        }
     }
 }
 
@@ -243,29 +337,29 @@ impl Default for GhostTimer {
 
 fn auto_ghost(
     has_ghost: Query<&Ghost>,
-    player: Query<&Transform, With<RealPlayer>>,
-    mut count_down: Local<GhostTimer>,
+    players: Query<(&RealPlayer, &Transform, &SpawnPoint)>,
+    mut count_downs: Local<HashMap<usize, GhostTimer>>,
     mut events: ParamSet<(EventReader<GhostEvents>, EventWriter<GhostEvents>)>,
     time: Res<Time>,
 ) {
     for event in events.p0().iter() {
-        match event {
-            GhostEvents::ClearGhosts => {
-                count_down.0.reset();
-            }
-            _ => {}
+        if let GhostEvents::ClearGhosts(slot) = event {
+            count_downs.entry(*slot).or_default().0.reset();
         }
     }
-    if let Err(QuerySingleError::NoEntities(_)) = has_ghost.get_single() {
-        let player = player.single();
-        if player.translation.distance(Vec3::ZERO) < 8. && !count_down.0.finished() {
-            events.p1().send(GhostEvents::ClearTrail);
+    for (&RealPlayer(slot), transform, spawn) in &players {
+        if has_ghost.iter().any(|ghost| ghost.owner == slot) {
+            continue;
+        }
+        let count_down = count_downs.entry(slot).or_default();
+        if transform.translation.distance(spawn.0) < 8. && !count_down.0.finished() {
+            events.p1().send(GhostEvents::ClearTrail(slot));
             count_down.0.reset();
-            return;
+            continue;
         }
         count_down.0.tick(time.delta());
         if count_down.0.finished() {
-            events.p1().send(GhostEvents::SpawnGhost);
+            events.p1().send(GhostEvents::SpawnGhost(slot));
         }
     }
 }
@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+
+use bevy::{
+    prelude::{
+        info, App, Component, DetectChangesMut, Entity, EventReader, EventWriter, IntoSystemConfig,
+        IntoSystemSetConfig, NextState, Plugin, Query, ResMut, SystemSet, Transform,
+    },
+    reflect::Reflect,
+};
+use bevy_rapier2d::prelude::Velocity;
+
+use crate::{
+    ghost::GhostEvents,
+    map::LoadedLevel,
+    player::{RealPlayer, SpawnPoint},
+    GameState, Score,
+};
+
+pub const STARTING_LIVES: usize = 3;
+
+/// Systems that send [`PlayerDied`] go in `Kill` so the death is handled the same frame
+#[derive(Debug, PartialEq, Eq, Hash, Clone, SystemSet)]
+pub enum LivesStages {
+    Kill,
+    Respawn,
+}
+
+pub struct LivesPlugin;
+
+impl Plugin for LivesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerDied>()
+            .configure_set(LivesStages::Kill.before(LivesStages::Respawn))
+            .add_system(respawn_players.in_set(LivesStages::Respawn))
+            .register_type::<Lives>();
+    }
+}
+
+/// Deaths a real player has left before the game is over
+#[derive(Component, Reflect)]
+pub struct Lives(pub usize);
+
+impl Default for Lives {
+    fn default() -> Self {
+        Lives(STARTING_LIVES)
+    }
+}
+
+/// Sent for a real player that was killed, whatever killed them
+pub struct PlayerDied(pub Entity);
+
+/// takes a life and puts the player back at their spawn point,
+/// the level starts over once someone has no lives left
+fn respawn_players(
+    mut deaths: EventReader<PlayerDied>,
+    mut players: Query<(
+        &RealPlayer,
+        &mut Lives,
+        &mut Score,
+        &SpawnPoint,
+        &mut Transform,
+        &mut Velocity,
+    )>,
+    mut events: EventWriter<GhostEvents>,
+    mut loaded_level: ResMut<LoadedLevel>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    // two things can kill the same player in one frame, that is still one life
+    let dead: HashSet<Entity> = deaths.iter().map(|PlayerDied(entity)| *entity).collect();
+    let mut game_over = false;
+    for entity in dead {
+        let Ok((&RealPlayer(slot), mut lives, mut score, spawn, mut pos, mut vel)) = players.get_mut(entity) else {continue;};
+        lives.0 = lives.0.saturating_sub(1);
+        info!(
+            "Player {} died with score {}, {} lives left",
+            slot, score.0, lives.0
+        );
+        score.0 = 0;
+        events.send(GhostEvents::ClearGhosts(slot));
+        events.send(GhostEvents::ClearTrail(slot));
+        *vel = Velocity::zero();
+        *pos = Transform::from_translation(spawn.0);
+        game_over |= lives.0 == 0;
+    }
+    if !game_over {
+        return;
+    }
+    info!("Game over");
+    for (_, mut lives, mut score, _, _, _) in &mut players {
+        *lives = Lives::default();
+        score.0 = 0;
+    }
+    // reloading puts everyone back at the start and forgets the checkpoints
+    loaded_level.set_changed();
+    let _ = state.set(GameState::Menu);
+}
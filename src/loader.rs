@@ -1,11 +1,21 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
 use bevy::{
-    asset::{AssetLoader, LoadedAsset},
-    prelude::Vec2,
+    asset::{AssetLoader, AssetPath, LoadedAsset},
+    math::Rect,
+    prelude::{warn, Handle, Image, UVec2, Vec2},
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::{CompressedImageFormats, ImageType},
+    },
     sprite::TextureAtlas,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::Visitor, Deserialize, Serialize};
 
-use crate::animation::SpriteAnimation;
+use crate::animation::{FrameTag, PlaybackMode, SpriteAnimation};
 
 #[derive(Default)]
 pub struct AnimationLoader;
@@ -28,51 +38,646 @@ pub struct AnimationAsset {
     #[serde(default)]
     pub id: Option<String>,
     pub fps: f32,
+    #[serde(default)]
     pub tile_size: Vec2,
+    #[serde(default)]
     pub rows: usize,
+    #[serde(default)]
     pub columns: usize,
+    #[serde(default)]
     pub texture_path: String,
+    /// loose frame images used instead of a sheet, one cell each
+    #[serde(default)]
+    pub frames: Vec<String>,
+    /// entries of a file with the same pack share one atlas built at load time
+    #[serde(default)]
+    pub pack: Option<String>,
+    #[serde(default)]
+    pub mode: PlaybackMode,
+    /// seconds to show each frame for, frames not listed use `1 / fps`
+    #[serde(default)]
+    pub frame_times: Option<Vec<f32>>,
+    #[serde(default)]
+    pub events: Vec<FrameTag>,
+    /// gap between cells of the sheet
+    #[serde(default)]
+    pub padding: Option<Vec2>,
+    /// position of the first cell in the sheet
+    #[serde(default)]
+    pub offset: Option<Vec2>,
+    /// first cell of the sheet used by this clip
+    #[serde(default)]
+    pub start: usize,
+    /// last cell of the sheet used by this clip, defaults to the last cell
+    #[serde(default)]
+    pub end: Option<usize>,
+}
+
+impl AnimationAsset {
+    /// number of cells in the sheet, or of loose frames
+    pub fn cells(&self) -> usize {
+        if self.frames.is_empty() {
+            self.rows * self.columns
+        } else {
+            self.frames.len()
+        }
+    }
+
+    /// the cells of the sheet that make up this clip
+    pub fn frame_range(&self) -> std::ops::Range<usize> {
+        let cells = self.cells();
+        let end = self.end.map_or(cells, |end| (end + 1).min(cells));
+        self.start.min(end)..end
+    }
+
+    /// checks the fields that do not need the rest of the file or the asset folder
+    pub fn validate(&self) -> Result<(), (&'static str, AnimationError)> {
+        if !self.fps.is_finite() || self.fps <= 0. {
+            return Err(("fps", AnimationError::NotPositive(self.fps)));
+        }
+        if self.frames.is_empty() {
+            if !self.tile_size.cmpgt(Vec2::ZERO).all() {
+                return Err((
+                    "tile_size",
+                    AnimationError::NotPositive(self.tile_size.min_element()),
+                ));
+            }
+            if self.rows == 0 {
+                return Err(("rows", AnimationError::Zero));
+            }
+            if self.columns == 0 {
+                return Err(("columns", AnimationError::Zero));
+            }
+        }
+        let cells = self.cells();
+        if let Some(end) = self.end {
+            if end >= cells {
+                return Err((
+                    "end",
+                    AnimationError::OutOfRange {
+                        value: end,
+                        len: cells,
+                    },
+                ));
+            }
+        }
+        let frames = self.frame_range();
+        if frames.is_empty() {
+            return Err((
+                "start",
+                AnimationError::OutOfRange {
+                    value: self.start,
+                    len: frames.end,
+                },
+            ));
+        }
+        if let Some(time) = self
+            .frame_times
+            .iter()
+            .flatten()
+            .find(|time| time.is_nan() || **time <= 0.)
+        {
+            return Err(("frame_times", AnimationError::NotPositive(*time)));
+        }
+        if let Some(tag) = self.events.iter().find(|tag| tag.frame >= frames.len()) {
+            return Err((
+                "events",
+                AnimationError::OutOfRange {
+                    value: tag.frame,
+                    len: frames.len(),
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    /// images this entry reads its cells from
+    pub fn texture_paths(&self) -> Vec<&str> {
+        if self.frames.is_empty() {
+            vec![self.texture_path.as_str()]
+        } else {
+            self.frames.iter().map(String::as_str).collect()
+        }
+    }
+
+    /// the atlas this entry is packed into, loose frames are always packed
+    pub fn pack_group(&self) -> Option<String> {
+        match (&self.pack, &self.id) {
+            (Some(pack), _) => Some(pack.clone()),
+            _ if self.frames.is_empty() => None,
+            (None, Some(id)) => Some(id.clone()),
+            (None, None) => Some(String::new()),
+        }
+    }
+}
+
+/// `{suffix}` for the default entry or pack, `{name}_{suffix}` otherwise
+fn label(name: &str, suffix: &str) -> String {
+    if name.is_empty() {
+        suffix.to_string()
+    } else {
+        format!("{}_{}", name, suffix)
+    }
+}
+
+#[derive(Debug)]
+pub enum AnimationError {
+    NotPositive(f32),
+    Zero,
+    OutOfRange { value: usize, len: usize },
+    DuplicateId(Option<String>),
+    MissingTexture(String),
+    UnreadableTexture { path: String, reason: String },
 }
 
+impl std::fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnimationError::NotPositive(v) => write!(f, "must be more than 0, got {}", v),
+            AnimationError::Zero => f.write_str("can't be 0"),
+            AnimationError::OutOfRange { value, len } => {
+                write!(f, "{} is out of range, only {} frames", value, len)
+            }
+            AnimationError::DuplicateId(Some(id)) => write!(f, "{:?} is used more than once", id),
+            AnimationError::DuplicateId(None) => f.write_str("only one entry can leave out the id"),
+            AnimationError::MissingTexture(path) => write!(f, "{:?} does not exist", path),
+            AnimationError::UnreadableTexture { path, reason } => {
+                write!(f, "{:?} can't be packed: {}", path, reason)
+            }
+        }
+    }
+}
+
+/// Says which file, entry and field of a `.san.ron` is wrong
+#[derive(Debug)]
+pub struct AnimationLoadError {
+    pub path: PathBuf,
+    pub entry: usize,
+    pub field: &'static str,
+    pub error: AnimationError,
+}
+
+impl std::fmt::Display for AnimationLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} entry {}: `{}` {}",
+            self.path, self.entry, self.field, self.error
+        )
+    }
+}
+
+impl std::error::Error for AnimationLoadError {}
+
 async fn load_antimation<'de, 'a, 'b>(
     bytes: &'a [u8],
     context: &'a mut bevy::asset::LoadContext<'b>,
 ) -> Result<(), bevy::asset::Error> {
     let assets: Vec<AnimationAsset> = ron::de::from_bytes(bytes)?;
 
-    for asset_data in assets {
-        let image_handle = context.get_handle(&asset_data.texture_path);
+    let mut ids = HashSet::new();
+    for (entry, asset_data) in assets.iter().enumerate() {
+        let fail = |field, error| AnimationLoadError {
+            path: context.path().to_path_buf(),
+            entry,
+            field,
+            error,
+        };
+        asset_data
+            .validate()
+            .map_err(|(field, error)| fail(field, error))?;
+        if !ids.insert(asset_data.id.clone()) {
+            return Err(fail("id", AnimationError::DuplicateId(asset_data.id.clone())).into());
+        }
+        let field = if asset_data.frames.is_empty() {
+            "texture_path"
+        } else {
+            "frames"
+        };
+        for path in asset_data.texture_paths() {
+            if context.asset_io().get_metadata(Path::new(path)).is_err() {
+                return Err(fail(field, AnimationError::MissingTexture(path.to_string())).into());
+            }
+        }
+    }
 
-        let atlas = TextureAtlas::from_grid(
-            image_handle,
-            asset_data.tile_size,
-            asset_data.columns,
-            asset_data.rows,
-            None,
-            None,
+    let mut packs: Vec<(String, Vec<usize>)> = Vec::new();
+    for (entry, asset_data) in assets.iter().enumerate() {
+        let Some(group) = asset_data.pack_group() else {continue;};
+        match packs.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, entries)) => entries.push(entry),
+            None => packs.push((group, vec![entry])),
+        }
+    }
+    let mut packed = HashMap::new();
+    for (group, entries) in packs {
+        let (atlas, starts) = pack_atlas(&assets, &entries, &group, context).await?;
+        packed.extend(
+            entries
+                .into_iter()
+                .zip(starts)
+                .map(|(entry, start)| (entry, (atlas.clone(), start))),
         );
-        let loaded_atlas = LoadedAsset::new(atlas).with_dependency(asset_data.texture_path.into());
-        if let Some(id) = asset_data.id {
-            let atlas = context.set_labeled_asset(&format!("{}_Atlas", id), loaded_atlas);
+    }
 
-            let asset = SpriteAnimation {
-                len: asset_data.rows * asset_data.columns,
-                frame_time: 1. / asset_data.fps,
-                texture_atlas: atlas,
-            };
+    for (entry, asset_data) in assets.into_iter().enumerate() {
+        let frames = asset_data.frame_range();
+        let (atlas, start) = match packed.remove(&entry) {
+            Some(packed) => packed,
+            None => {
+                let image_handle = context.get_handle(&asset_data.texture_path);
+                let atlas = TextureAtlas::from_grid(
+                    image_handle,
+                    asset_data.tile_size,
+                    asset_data.columns,
+                    asset_data.rows,
+                    asset_data.padding,
+                    asset_data.offset,
+                );
+                let loaded_atlas =
+                    LoadedAsset::new(atlas).with_dependency(asset_data.texture_path.clone().into());
+                let name = asset_data.id.as_deref().unwrap_or_default();
+                let atlas = context.set_labeled_asset(&label(name, "Atlas"), loaded_atlas);
+                (atlas, frames.start)
+            }
+        };
 
+        let asset = SpriteAnimation {
+            start,
+            len: frames.len(),
+            frame_time: 1. / asset_data.fps,
+            frame_times: asset_data.frame_times.unwrap_or_default(),
+            mode: asset_data.mode,
+            events: asset_data.events,
+            texture_atlas: atlas,
+        };
+        if let Some(id) = asset_data.id {
             context.set_labeled_asset(&id, LoadedAsset::new(asset));
         } else {
-            let atlas = context.set_labeled_asset("Atlas", loaded_atlas);
+            context.set_default_asset(LoadedAsset::new(asset));
+        }
+    }
+    Ok(())
+}
+
+/// A part of a decoded image that is copied into a packed atlas
+struct Cell {
+    image: usize,
+    min: UVec2,
+    size: UVec2,
+}
 
-            let asset = SpriteAnimation {
-                len: asset_data.rows * asset_data.columns,
-                frame_time: 1. / asset_data.fps,
-                texture_atlas: atlas,
+/// Places cells of the given sizes on shelves, returns the size of the atlas and where each cell goes
+pub fn pack_cells(sizes: &[UVec2]) -> (UVec2, Vec<UVec2>) {
+    let area: u32 = sizes.iter().map(|size| size.x * size.y).sum();
+    let widest = sizes.iter().map(|size| size.x).max().unwrap_or(0);
+    let width = ((area as f32).sqrt().ceil() as u32)
+        .max(widest)
+        .next_power_of_two();
+
+    // tallest first so every shelf wastes as little height as possible
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].y));
+
+    let mut positions = vec![UVec2::ZERO; sizes.len()];
+    let mut cursor = UVec2::ZERO;
+    let mut shelf_height = 0;
+    for i in order {
+        let size = sizes[i];
+        if cursor.x + size.x > width {
+            cursor = UVec2::new(0, cursor.y + shelf_height);
+            shelf_height = 0;
+        }
+        positions[i] = cursor;
+        cursor.x += size.x;
+        shelf_height = shelf_height.max(size.y);
+    }
+    (UVec2::new(width, cursor.y + shelf_height), positions)
+}
+
+/// Builds one atlas out of the cells of every entry in `entries`,
+/// returns it with the index of the first cell of each entry
+async fn pack_atlas<'a, 'b>(
+    assets: &[AnimationAsset],
+    entries: &[usize],
+    group: &str,
+    context: &'a mut bevy::asset::LoadContext<'b>,
+) -> Result<(Handle<TextureAtlas>, Vec<usize>), bevy::asset::Error> {
+    let mut images: Vec<Image> = Vec::new();
+    let mut decoded: HashMap<&str, usize> = HashMap::new();
+    let mut cells = Vec::new();
+    let mut starts = Vec::new();
+
+    for &entry in entries {
+        let asset_data = &assets[entry];
+        let fail = |path: &str, reason: String| AnimationLoadError {
+            path: context.path().to_path_buf(),
+            entry,
+            field: if asset_data.frames.is_empty() {
+                "texture_path"
+            } else {
+                "frames"
+            },
+            error: AnimationError::UnreadableTexture {
+                path: path.to_string(),
+                reason,
+            },
+        };
+        starts.push(cells.len());
+
+        for frame in asset_data.frame_range() {
+            let (path, grid_cell) = match asset_data.frames.get(frame) {
+                Some(path) => (path.as_str(), None),
+                None => (asset_data.texture_path.as_str(), Some(frame)),
             };
-            context.set_default_asset(LoadedAsset::new(asset));
+            let image = match decoded.get(path) {
+                Some(image) => *image,
+                None => {
+                    let bytes = context
+                        .read_asset_bytes(path)
+                        .await
+                        .map_err(|e| fail(path, e.to_string()))?;
+                    let extension = Path::new(path)
+                        .extension()
+                        .and_then(|e| e.to_str())
+                        .unwrap_or("png");
+                    let image = Image::from_buffer(
+                        &bytes,
+                        ImageType::Extension(extension),
+                        CompressedImageFormats::NONE,
+                        true,
+                    )
+                    .map_err(|e| fail(path, e.to_string()))?
+                    .convert(TextureFormat::Rgba8UnormSrgb)
+                    .ok_or_else(|| fail(path, "unsupported pixel format".to_string()))?;
+                    images.push(image);
+                    decoded.insert(path, images.len() - 1);
+                    images.len() - 1
+                }
+            };
+            let image_size = images[image].size().as_uvec2();
+            let (min, size) = match grid_cell {
+                Some(index) => {
+                    let column = (index % asset_data.columns) as f32;
+                    let row = (index / asset_data.columns) as f32;
+                    let step = asset_data.tile_size + asset_data.padding.unwrap_or_default();
+                    let min = asset_data.offset.unwrap_or_default() + step * Vec2::new(column, row);
+                    (min.as_uvec2(), asset_data.tile_size.as_uvec2())
+                }
+                None => (UVec2::ZERO, image_size),
+            };
+            if (min + size).cmpgt(image_size).any() {
+                return Err(fail(
+                    path,
+                    format!("cell {} is outside the {} image", frame, image_size),
+                )
+                .into());
+            }
+            cells.push(Cell { image, min, size });
+        }
+    }
+
+    let sizes: Vec<UVec2> = cells.iter().map(|cell| cell.size).collect();
+    let (atlas_size, positions) = pack_cells(&sizes);
+    let mut data = vec![0; (atlas_size.x * atlas_size.y * 4) as usize];
+    for (cell, position) in cells.iter().zip(positions.iter()) {
+        let image = &images[cell.image];
+        let width = image.texture_descriptor.size.width;
+        let len = (cell.size.x * 4) as usize;
+        for row in 0..cell.size.y {
+            let src = (((cell.min.y + row) * width + cell.min.x) * 4) as usize;
+            let dst = (((position.y + row) * atlas_size.x + position.x) * 4) as usize;
+            data[dst..dst + len].copy_from_slice(&image.data[src..src + len]);
         }
     }
+
+    let image = Image::new(
+        Extent3d {
+            width: atlas_size.x,
+            height: atlas_size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // the packed image is only rebuilt on a hot reload if the source images are dependencies
+    let sources: Vec<AssetPath<'static>> =
+        decoded.keys().map(|path| path.to_string().into()).collect();
+    let image = context.set_labeled_asset(
+        &label(group, "Image"),
+        LoadedAsset::new(image).with_dependencies(sources.clone()),
+    );
+    let mut atlas = TextureAtlas::new_empty(image, atlas_size.as_vec2());
+    for (cell, position) in cells.iter().zip(positions) {
+        atlas.add_texture(Rect::from_corners(
+            position.as_vec2(),
+            (position + cell.size).as_vec2(),
+        ));
+    }
+    let atlas = context.set_labeled_asset(
+        &label(group, "Atlas"),
+        LoadedAsset::new(atlas).with_dependencies(sources),
+    );
+    Ok((atlas, starts))
+}
+
+/// Loads the JSON sheet exported by Aseprite, every frame tag becomes a labeled clip
+#[derive(Default)]
+pub struct AsepriteLoader;
+
+impl AssetLoader for AsepriteLoader {
+    fn extensions(&self) -> &[&str] {
+        &["aseprite.json"]
+    }
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move { load_aseprite(bytes, load_context).await })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AsepriteSheet {
+    pub frames: AsepriteFrames,
+    pub meta: AsepriteMeta,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AsepriteFrame {
+    pub frame: AsepriteRect,
+    /// milliseconds
+    pub duration: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AsepriteRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AsepriteSize {
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AsepriteMeta {
+    pub image: String,
+    pub size: AsepriteSize,
+    #[serde(default, rename = "frameTags")]
+    pub frame_tags: Vec<AsepriteTag>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AsepriteTag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    #[serde(default)]
+    pub direction: String,
+    #[serde(default)]
+    pub repeat: Option<String>,
+}
+
+impl AsepriteTag {
+    fn mode(&self) -> PlaybackMode {
+        match (self.direction.as_str(), self.repeat.as_deref()) {
+            ("pingpong", _) => PlaybackMode::PingPong,
+            (_, Some("1")) => PlaybackMode::Clamp,
+            ("forward" | "", _) => PlaybackMode::Loop,
+            (direction, _) => {
+                warn!("{} playback is not supported, using forward", direction);
+                PlaybackMode::Loop
+            }
+        }
+    }
+}
+
+/// Frames in sheet order, from either the "Array" or the "Hash" export
+#[derive(Debug)]
+pub struct AsepriteFrames(pub Vec<AsepriteFrame>);
+
+impl<'de> Deserialize<'de> for AsepriteFrames {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(AsepriteFramesVisitor)
+    }
+}
+
+struct AsepriteFramesVisitor;
+
+impl<'de> Visitor<'de> for AsepriteFramesVisitor {
+    type Value = AsepriteFrames;
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("Expected a list or map of frames")
+    }
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut frames = Vec::new();
+        while let Some(frame) = seq.next_element()? {
+            frames.push(frame);
+        }
+        Ok(AsepriteFrames(frames))
+    }
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut frames = Vec::new();
+        while let Some((_, frame)) = map.next_entry::<String, AsepriteFrame>()? {
+            frames.push(frame);
+        }
+        Ok(AsepriteFrames(frames))
+    }
+}
+
+async fn load_aseprite<'a, 'b>(
+    bytes: &'a [u8],
+    context: &'a mut bevy::asset::LoadContext<'b>,
+) -> Result<(), bevy::asset::Error> {
+    let sheet: AsepriteSheet = serde_json::from_slice(bytes)?;
+    let fail = |entry, field, error| AnimationLoadError {
+        path: context.path().to_path_buf(),
+        entry,
+        field,
+        error,
+    };
+
+    // the image is next to the json file
+    let texture_path = context
+        .path()
+        .parent()
+        .map_or_else(PathBuf::new, Path::to_path_buf)
+        .join(&sheet.meta.image);
+    if context.asset_io().get_metadata(&texture_path).is_err() {
+        let error = AnimationError::MissingTexture(sheet.meta.image.clone());
+        return Err(fail(0, "image", error).into());
+    }
+    let frames = sheet.frames.0;
+    if frames.is_empty() {
+        return Err(fail(0, "frames", AnimationError::Zero).into());
+    }
+    let mut names = HashSet::new();
+    for (entry, tag) in sheet.meta.frame_tags.iter().enumerate() {
+        if tag.to >= frames.len() || tag.from > tag.to {
+            let error = AnimationError::OutOfRange {
+                value: tag.to.max(tag.from),
+                len: frames.len(),
+            };
+            return Err(fail(entry, "frameTags", error).into());
+        }
+        if !names.insert(&tag.name) {
+            let error = AnimationError::DuplicateId(Some(tag.name.clone()));
+            return Err(fail(entry, "frameTags", error).into());
+        }
+    }
+
+    let mut atlas = TextureAtlas::new_empty(
+        context.get_handle(AssetPath::from(texture_path.as_path())),
+        Vec2::new(sheet.meta.size.w, sheet.meta.size.h),
+    );
+    for frame in frames.iter() {
+        let AsepriteRect { x, y, w, h } = frame.frame;
+        atlas.add_texture(Rect::new(x, y, x + w, y + h));
+    }
+    let atlas = context.set_labeled_asset(
+        "Atlas",
+        LoadedAsset::new(atlas).with_dependency(texture_path.into()),
+    );
+    let clip = |start: usize, end: usize, mode: PlaybackMode| SpriteAnimation {
+        start,
+        len: end + 1 - start,
+        frame_time: frames[start].duration / 1000.,
+        frame_times: frames[start..=end]
+            .iter()
+            .map(|frame| frame.duration / 1000.)
+            .collect(),
+        mode,
+        events: Vec::new(),
+        texture_atlas: atlas.clone(),
+    };
+
+    for tag in sheet.meta.frame_tags.iter() {
+        context.set_labeled_asset(
+            &tag.name,
+            LoadedAsset::new(clip(tag.from, tag.to, tag.mode())),
+        );
+    }
+    context.set_default_asset(LoadedAsset::new(clip(
+        0,
+        frames.len() - 1,
+        PlaybackMode::Loop,
+    )));
     Ok(())
 }
 
@@ -85,9 +690,125 @@ fn ron() {
         rows: 5,
         columns: 10,
         texture_path: "SomePath/".to_string(),
+        frames: Vec::new(),
+        pack: None,
+        mode: PlaybackMode::PingPong,
+        frame_times: Some(vec![0.1, 0.05]),
+        events: vec![FrameTag {
+            frame: 3,
+            name: "footstep".to_string(),
+        }],
+        padding: Some(Vec2::splat(2.)),
+        offset: None,
+        start: 10,
+        end: Some(19),
     };
+    assert_eq!(animation.frame_range(), 10..20);
+    assert!(animation.validate().is_ok());
     println!(
         "{:?}",
         ron::ser::to_string_pretty(&vec![animation], ron::ser::PrettyConfig::default())
     );
 }
+
+#[test]
+fn validate() {
+    let animation = |fps: f32, rows: usize| AnimationAsset {
+        id: None,
+        fps,
+        tile_size: Vec2::splat(32.),
+        rows,
+        columns: 4,
+        texture_path: "SomePath/".to_string(),
+        frames: Vec::new(),
+        pack: None,
+        mode: PlaybackMode::Loop,
+        frame_times: None,
+        events: Vec::new(),
+        padding: None,
+        offset: None,
+        start: 0,
+        end: None,
+    };
+    assert!(animation(20., 1).validate().is_ok());
+    assert!(matches!(
+        animation(0., 1).validate(),
+        Err(("fps", AnimationError::NotPositive(_)))
+    ));
+    assert!(matches!(
+        animation(20., 0).validate(),
+        Err(("rows", AnimationError::Zero))
+    ));
+    let mut out_of_range = animation(20., 1);
+    out_of_range.end = Some(4);
+    assert!(matches!(
+        out_of_range.validate(),
+        Err(("end", AnimationError::OutOfRange { value: 4, len: 4 }))
+    ));
+}
+
+#[test]
+fn packing() {
+    let sizes = [UVec2::new(32, 32), UVec2::new(16, 16), UVec2::new(32, 32)];
+    let (size, positions) = pack_cells(&sizes);
+    let rects: Vec<Rect> = sizes
+        .iter()
+        .zip(positions.iter())
+        .map(|(size, position)| {
+            Rect::from_corners(position.as_vec2(), (*position + *size).as_vec2())
+        })
+        .collect();
+    for (i, rect) in rects.iter().enumerate() {
+        assert!(rect.max.cmple(size.as_vec2()).all());
+        for other in rects[i + 1..].iter() {
+            assert!(rect.intersect(*other).is_empty());
+        }
+    }
+
+    let loose: AnimationAsset = ron::de::from_str(
+        r#"(
+            id: Some("Pop"),
+            fps: 10.,
+            frames: ["Items/Pop 1.png", "Items/Pop 2.png", "Items/Pop 3.png"],
+            end: Some(1),
+        )"#,
+    )
+    .expect("valid entry");
+    assert!(loose.validate().is_ok());
+    assert_eq!(loose.frame_range(), 0..2);
+    assert_eq!(loose.pack_group(), Some("Pop".to_string()));
+}
+
+#[test]
+fn aseprite_json() {
+    let sheet: AsepriteSheet = serde_json::from_str(
+        r#"{
+            "frames": {
+                "run 0.aseprite": { "frame": { "x": 0, "y": 0, "w": 32, "h": 32 }, "duration": 100 },
+                "run 1.aseprite": { "frame": { "x": 32, "y": 0, "w": 32, "h": 32 }, "duration": 50 },
+                "run 2.aseprite": { "frame": { "x": 64, "y": 0, "w": 32, "h": 32 }, "duration": 50 }
+            },
+            "meta": {
+                "image": "run.png",
+                "size": { "w": 96, "h": 32 },
+                "frameTags": [
+                    { "name": "Run", "from": 0, "to": 1, "direction": "forward" },
+                    { "name": "Land", "from": 2, "to": 2, "direction": "forward", "repeat": "1" },
+                    { "name": "Bob", "from": 0, "to": 2, "direction": "pingpong" }
+                ]
+            }
+        }"#,
+    )
+    .expect("valid sheet");
+    assert_eq!(sheet.frames.0.len(), 3);
+    assert_eq!(sheet.frames.0[1].frame.x, 32.);
+    let modes: Vec<_> = sheet.meta.frame_tags.iter().map(|tag| tag.mode()).collect();
+    assert_eq!(
+        modes,
+        vec![
+            PlaybackMode::Loop,
+            PlaybackMode::Clamp,
+            PlaybackMode::PingPong
+        ]
+    );
+}
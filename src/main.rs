@@ -1,30 +1,44 @@
 mod animation;
+mod animation_graph;
 mod camera;
+mod characters;
+mod controller;
+mod controls;
 mod editor;
 mod ghost;
+mod lives;
 mod loader;
 mod map;
 mod menu;
+mod movement;
 mod player;
+mod roster;
 mod user_input;
 
 use bevy::prelude::{
-    App, Component, DefaultPlugins, ImagePlugin, PluginGroup, Resource, States, TextureAtlasSprite,
-    Vec2,
+    App, AssetPlugin, Component, DefaultPlugins, ImagePlugin, PluginGroup, States,
+    TextureAtlasSprite, Vec2,
 };
 use bevy_editor_pls::EditorPlugin;
-use bevy_rapier2d::prelude::{NoUserData, RapierConfiguration, RapierPhysicsPlugin, TimestepMode};
+use bevy_rapier2d::prelude::{RapierConfiguration, RapierPhysicsPlugin, TimestepMode};
 use leafwing_input_manager::prelude::InputManagerPlugin;
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
+        .add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(AssetPlugin {
+                    watch_for_changes: true,
+                    ..Default::default()
+                }),
+        )
         .add_plugin(EditorPlugin::new())
         .add_plugin(animation::PhoxAnimationPlugin)
         .add_startup_system(camera::spawn_cam)
         .register_type::<TextureAtlasSprite>()
         .insert_resource(RapierConfiguration {
-            gravity: Vec2::Y * -294.,
+            gravity: Vec2::Y * movement::MovementConfig::default().gravity,
             timestep_mode: TimestepMode::Fixed {
                 dt: 1. / 60.,
                 substeps: 1,
@@ -32,19 +46,24 @@ fn main() {
             ..Default::default()
         })
         .add_plugin(InputManagerPlugin::<user_input::PlayerInput>::default())
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(16.))
+        .add_plugin(RapierPhysicsPlugin::<map::OneWayPlatformHooks>::pixels_per_meter(16.))
+        .add_plugin(movement::MovementPlugin)
+        .add_plugin(controls::ControlsPlugin)
         .add_plugin(player::PlayerPlugin)
+        .add_plugin(controller::ControllerPlugin)
         .add_plugin(map::MapPlugin)
         .add_plugin(ghost::GhostPlugin)
-        .insert_resource(Score(0))
+        .add_plugin(lives::LivesPlugin)
         .add_state::<GameState>()
+        .add_plugin(roster::RosterPlugin)
         .add_plugin(menu::MenuPlugin)
         .add_plugin(editor::LevelEditorPlugin)
         .run()
 }
 
-#[derive(Resource)]
-pub struct Score(usize);
+/// Collectables picked up by a real player since they last died
+#[derive(Component, Default)]
+pub struct Score(pub usize);
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Component, States)]
 enum GameState {
@@ -54,4 +73,6 @@ enum GameState {
     InputLevelBase64,
     InputLevelName,
     LevelEditor,
+    CharacterSelect,
+    Controls,
 }
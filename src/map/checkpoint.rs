@@ -0,0 +1,109 @@
+use bevy::{
+    prelude::{
+        Color, Commands, Component, Entity, IVec2, Name, Query, Res, Transform, Vec2, Vec3, With,
+    },
+    reflect::Reflect,
+    sprite::TextureAtlasSprite,
+};
+use bevy_rapier2d::prelude::{Collider, RapierContext, RigidBody, Sensor};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{Animation, Animations},
+    player::{RealPlayer, SpawnPoint},
+};
+
+use super::{
+    tile_map::{MapData, MapObject, TerrainMaterial, TerrainType},
+    CellBundle,
+};
+
+/// A post that becomes the spawn point of every real player that touches it
+#[derive(Clone, Copy, Deserialize, Serialize, Reflect, Component)]
+pub struct Checkpoint {
+    /// the cell the post stands in, it is two cells tall
+    pub offset: IVec2,
+}
+
+const UNREACHED_COLOR: Color = Color::GRAY;
+const REACHED_COLOR: Color = Color::LIME_GREEN;
+
+impl Checkpoint {
+    /// where the middle of a player standing at the post is
+    fn spawn_point(&self) -> Vec3 {
+        Vec3::new(
+            self.offset.x as f32 * 16.,
+            self.offset.y as f32 * 16. + 8.,
+            0.,
+        )
+    }
+}
+
+pub fn reach_checkpoint(
+    mut players: Query<(Entity, &mut SpawnPoint), With<RealPlayer>>,
+    mut checkpoints: Query<(&Checkpoint, &mut TextureAtlasSprite)>,
+    rapier_context: Res<RapierContext>,
+) {
+    for (entity, mut spawn) in &mut players {
+        for (collider1, collider2, intersecting) in rapier_context.intersections_with(entity) {
+            if !intersecting {
+                continue;
+            }
+            let other = if collider1 == entity { collider2 } else { collider1 };
+            let Ok((checkpoint, mut sprite)) = checkpoints.get_mut(other) else {continue;};
+            if spawn.0 != checkpoint.spawn_point() {
+                spawn.0 = checkpoint.spawn_point();
+            }
+            if sprite.color != REACHED_COLOR {
+                sprite.color = REACHED_COLOR;
+            }
+        }
+    }
+}
+
+impl MapObject for Checkpoint {
+    fn spawn(
+        &self,
+        terrain: &Animations,
+        commands: &mut Commands,
+        _map_data: &mut MapData,
+    ) -> Option<Entity> {
+        let post = self.spawn_point() + Vec3::Z;
+        Some(
+            commands
+                .spawn((
+                    CellBundle {
+                        transform: Transform::from_translation(post),
+                        collider: Collider::cuboid(4., 16.),
+                        rigid_body: RigidBody::Fixed,
+                        sprite: TextureAtlasSprite {
+                            index: TerrainMaterial::Iron.to_sprite(TerrainType::OneVertical),
+                            color: UNREACHED_COLOR,
+                            custom_size: Some(Vec2::new(8., 32.)),
+                            ..Default::default()
+                        },
+                        texture_atlas: terrain
+                            .get_atlas(Animation::Terrain)
+                            .expect("Terrain is loaded"),
+                        ..Default::default()
+                    },
+                    Sensor,
+                    Name::new("Checkpoint"),
+                    *self,
+                ))
+                .id(),
+        )
+    }
+    fn object_type(&self) -> super::levels::MapObjectType {
+        super::levels::MapObjectType::Checkpoint
+    }
+    fn serialize(&self) -> bevy::reflect::serde::Serializable<'_> {
+        bevy::reflect::serde::Serializable::Borrowed(self)
+    }
+    fn clone(&self) -> Box<dyn MapObject> {
+        Box::new(<Self as Clone>::clone(self))
+    }
+    fn ui_draw(&self, _commands: &mut Commands, _root: Entity) {
+        todo!()
+    }
+}
@@ -1,7 +1,9 @@
+use std::collections::HashSet;
+
 use bevy::{
     prelude::{
-        default, error, Commands, Component, DespawnRecursiveExt, Entity, EventWriter, IVec2, Name,
-        Query, Res, ResMut, Transform, Vec3, With,
+        default, error, Assets, Commands, Component, DespawnRecursiveExt, Entity, EventReader,
+        EventWriter, IVec2, Name, Query, Res, Transform, Vec3, With,
     },
     reflect::Reflect,
 };
@@ -12,44 +14,68 @@ use serde::{Deserialize, Serialize};
 use crate::{
     animation::{Animation, Animations},
     ghost::GhostEvents,
+    lives::PlayerDied,
     player::RealPlayer,
     Score,
 };
 
 use super::{
+    levels::{Level, MapObjectType},
     tile_map::{MapData, MapEvent, MapObject},
-    CellBundle,
+    CellBundle, LoadedLevel,
 };
 
 pub fn get_collectable(
     mut commands: Commands,
-    player: Query<Entity, With<RealPlayer>>,
-    mut collectables: Query<&Collectable>,
+    mut players: Query<(Entity, &RealPlayer, &mut Score)>,
+    collectables: Query<&Collectable>,
     rapier_context: Res<RapierContext>,
     mut events: EventWriter<GhostEvents>,
     mut map_events: EventWriter<MapEvent>,
-    mut score: ResMut<Score>,
 ) {
-    let entity = player.single();
-    /* Iterate through all the intersection pairs involving a specific collider. */
-    for (collider1, collider2, intersecting) in rapier_context.intersections_with(entity) {
-        if intersecting {
-            if let Ok(collectable) = collectables.get_mut(collider2) {
-                events.send(GhostEvents::SpawnGhost);
-                map_events.send(MapEvent::spawn(Clone::clone(collectable)));
-                score.0 += 1;
-                commands.entity(collider2).despawn_recursive();
-            }
-            if let Ok(collectable) = collectables.get_mut(collider1) {
+    // two players touching the same fruit in one frame only collect it once
+    let mut collected = HashSet::new();
+    for (entity, &RealPlayer(slot), mut score) in &mut players {
+        /* Iterate through all the intersection pairs involving a specific collider. */
+        for (collider1, collider2, intersecting) in rapier_context.intersections_with(entity) {
+            if intersecting {
+                let other = if collider1 == entity { collider2 } else { collider1 };
+                if !collected.insert(other) {
+                    continue;
+                }
+                let Ok(collectable) = collectables.get(other) else {continue;};
+                events.send(GhostEvents::SpawnGhost(slot));
                 map_events.send(MapEvent::spawn(Clone::clone(collectable)));
-                events.send(GhostEvents::SpawnGhost);
                 score.0 += 1;
-                commands.entity(collider2).despawn_recursive();
+                commands.entity(other).despawn_recursive();
             }
         }
     }
 }
 
+/// a death takes the score away, so the fruit of the level is put back to collect again
+pub fn restore_collectables(
+    mut commands: Commands,
+    mut deaths: EventReader<PlayerDied>,
+    collectables: Query<Entity, With<Collectable>>,
+    levels: Res<Assets<Level>>,
+    current_level: Res<LoadedLevel>,
+    mut map_events: EventWriter<MapEvent>,
+) {
+    if deaths.iter().count() == 0 {
+        return;
+    }
+    let Some(level) = levels.get(&current_level.0) else {return;};
+    for entity in &collectables {
+        commands.entity(entity).despawn_recursive();
+    }
+    for obj in level.objects.iter() {
+        if matches!(obj.object_type(), MapObjectType::Collectable) {
+            map_events.send(MapEvent::Spawn(MapObject::clone(obj.as_ref())));
+        }
+    }
+}
+
 #[derive(Component, Clone, Deserialize, Serialize, Reflect)]
 pub struct Collectable {
     pub collectable_type: CollectableType,
@@ -195,3 +221,47 @@ impl MapObject for Collectable {
         todo!()
     }
 }
+
+#[test]
+fn kinematic_player_collects() {
+    use bevy::prelude::{App, MinimalPlugins, TransformBundle, TransformPlugin};
+    use bevy_rapier2d::prelude::{NoUserData, RapierPhysicsPlugin};
+
+    use crate::{controller::kinematic_body, movement::MovementConfig};
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_event::<GhostEvents>()
+        .add_event::<MapEvent>()
+        .add_system(get_collectable);
+    let player = app
+        .world
+        .spawn((
+            TransformBundle::default(),
+            Collider::cuboid(9., 16.),
+            kinematic_body(&MovementConfig::default()),
+            RealPlayer(0),
+            Score(0),
+        ))
+        .id();
+    let fruit = app
+        .world
+        .spawn((
+            TransformBundle::default(),
+            RigidBody::Fixed,
+            Collider::ball(8.),
+            Sensor,
+            Collectable {
+                collectable_type: CollectableType::Strawberry,
+                spawn_type: SpawnType::None,
+            },
+        ))
+        .id();
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(app.world.get::<Score>(player).map(|score| score.0), Some(1));
+    assert!(app.world.get_entity(fruit).is_none());
+}
@@ -0,0 +1,110 @@
+use bevy::{
+    prelude::{
+        AssetServer, Color, Commands, Component, Entity, EventWriter, IVec2, Name, Query, Res,
+        Transform, Vec2, Vec3, With,
+    },
+    reflect::Reflect,
+    sprite::TextureAtlasSprite,
+};
+use bevy_rapier2d::prelude::{Collider, RapierContext, RigidBody, Sensor};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{Animation, Animations},
+    player::RealPlayer,
+    roster::LevelCompleted,
+};
+
+use super::{
+    tile_map::{MapData, MapObject, TerrainMaterial, TerrainType},
+    CellBundle, LoadedLevel,
+};
+
+/// A post that completes the level for the first real player to touch it
+#[derive(Clone, Copy, Deserialize, Serialize, Reflect, Component)]
+pub struct Finish {
+    /// the cell the post stands in, it is two cells tall
+    pub offset: IVec2,
+}
+
+const UNREACHED_COLOR: Color = Color::GRAY;
+const REACHED_COLOR: Color = Color::GOLD;
+
+pub fn reach_finish(
+    players: Query<Entity, With<RealPlayer>>,
+    mut finishes: Query<&mut TextureAtlasSprite, With<Finish>>,
+    rapier_context: Res<RapierContext>,
+    loaded_level: Res<LoadedLevel>,
+    asset_server: Res<AssetServer>,
+    mut events: EventWriter<LevelCompleted>,
+) {
+    for entity in &players {
+        for (collider1, collider2, intersecting) in rapier_context.intersections_with(entity) {
+            if !intersecting {
+                continue;
+            }
+            let other = if collider1 == entity { collider2 } else { collider1 };
+            let Ok(mut sprite) = finishes.get_mut(other) else {continue;};
+            // the level counts once until it is loaded again
+            if sprite.color == REACHED_COLOR {
+                continue;
+            }
+            sprite.color = REACHED_COLOR;
+            // levels made in the editor have no path to remember them by
+            if let Some(path) = asset_server.get_handle_path(&loaded_level.0) {
+                events.send(LevelCompleted(path.path().to_string_lossy().into_owned()));
+            }
+        }
+    }
+}
+
+impl MapObject for Finish {
+    fn spawn(
+        &self,
+        terrain: &Animations,
+        commands: &mut Commands,
+        _map_data: &mut MapData,
+    ) -> Option<Entity> {
+        let post = Vec3::new(
+            self.offset.x as f32 * 16.,
+            self.offset.y as f32 * 16. + 8.,
+            1.,
+        );
+        Some(
+            commands
+                .spawn((
+                    CellBundle {
+                        transform: Transform::from_translation(post),
+                        collider: Collider::cuboid(4., 16.),
+                        rigid_body: RigidBody::Fixed,
+                        sprite: TextureAtlasSprite {
+                            index: TerrainMaterial::Gold.to_sprite(TerrainType::OneVertical),
+                            color: UNREACHED_COLOR,
+                            custom_size: Some(Vec2::new(8., 32.)),
+                            ..Default::default()
+                        },
+                        texture_atlas: terrain
+                            .get_atlas(Animation::Terrain)
+                            .expect("Terrain is loaded"),
+                        ..Default::default()
+                    },
+                    Sensor,
+                    Name::new("Finish"),
+                    *self,
+                ))
+                .id(),
+        )
+    }
+    fn object_type(&self) -> super::levels::MapObjectType {
+        super::levels::MapObjectType::Finish
+    }
+    fn serialize(&self) -> bevy::reflect::serde::Serializable<'_> {
+        bevy::reflect::serde::Serializable::Borrowed(self)
+    }
+    fn clone(&self) -> Box<dyn MapObject> {
+        Box::new(<Self as Clone>::clone(self))
+    }
+    fn ui_draw(&self, _commands: &mut Commands, _root: Entity) {
+        todo!()
+    }
+}
@@ -6,7 +6,7 @@ use crate::map::{
 #[allow(unused_imports)]
 use bevy::{
     asset::{AssetLoader, LoadedAsset},
-    prelude::{IVec2, IVec3},
+    prelude::{IVec2, IVec3, Vec2},
     reflect::TypeUuid,
 };
 #[allow(unused_imports)]
@@ -16,16 +16,34 @@ use serde::{
     Deserialize, Serialize,
 };
 
-use super::{collectable::Collectable, square::MapBox, tile_map::MapObject};
+use super::{
+    checkpoint::Checkpoint, collectable::Collectable, finish::Finish, platform::OneWayPlatform,
+    square::MapBox, tile_map::MapObject,
+};
 
 #[derive(TypeUuid, Default)]
 #[uuid = "e6b53f1c-9471-465c-b411-7729177acb9e"]
 pub struct Level {
     pub player_start: IVec2,
     pub objects: Vec<Box<dyn MapObject>>,
+    /// leaving this box kills, `None` is no limit
+    pub bounds: Option<Bounds>,
+    /// falling below this kills, `None` is no limit
+    pub kill_height: Option<i32>,
+}
+
+/// Corners of the playable area, in pixels like `player_start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Bounds {
+    pub min: IVec2,
+    pub max: IVec2,
 }
 
-const CURRENT_VERSION: u8 = 0;
+const CURRENT_VERSION: u8 = 1;
+
+/// the fields of each version, the older versions stop early
+const FIELDS_V0: &[&str] = &["start", "objects"];
+const FIELDS: &[&str] = &["start", "objects", "bounds", "kill_height"];
 
 #[allow(deprecated, dead_code)]
 impl Level {
@@ -34,6 +52,9 @@ impl Level {
         let Some(version) = bytes.first() else {return Err(anyhow::anyhow!("Need atleast one char in string"))};
         match version {
             0 => Ok(bincode::options()
+                .with_varint_encoding()
+                .deserialize_seed(LevelSeed(FIELDS_V0), &bytes[1..])?),
+            1 => Ok(bincode::options()
                 .with_varint_encoding()
                 .deserialize(&bytes[1..])?),
             _ => Err(anyhow::anyhow!("Unsuported version: {}", version)),
@@ -46,11 +67,26 @@ impl Level {
             .serialize_into(&mut bytes, &self)?;
         Ok(base64::encode(bytes))
     }
+    /// false once `point` is below the kill height or outside the bounds
+    pub fn in_bounds(&self, point: Vec2) -> bool {
+        if let Some(kill_height) = self.kill_height {
+            if point.y < kill_height as f32 {
+                return false;
+            }
+        }
+        self.bounds.map_or(true, |bounds| {
+            point.cmpge(bounds.min.as_vec2()).all() && point.cmple(bounds.max.as_vec2()).all()
+        })
+    }
 }
 
 impl PartialEq for Level {
     fn eq(&self, other: &Self) -> bool {
-        if self.player_start != other.player_start || self.objects.len() != other.objects.len() {
+        if self.player_start != other.player_start
+            || self.objects.len() != other.objects.len()
+            || self.bounds != other.bounds
+            || self.kill_height != other.kill_height
+        {
             return false;
         }
         for (object0, object1) in self.objects.iter().zip(other.objects.iter()) {
@@ -67,15 +103,30 @@ impl<'de> Deserialize<'de> for Level {
     where
         D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_struct("Level", &["player_start", "objects"], LevelVisitor)
+        deserializer.deserialize_struct("Level", FIELDS, LevelVisitor)
+    }
+}
+
+/// Reads a level with the fields of an older version
+struct LevelSeed(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for LevelSeed {
+    type Value = Level;
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("Level", self.0, LevelVisitor)
     }
 }
 
 #[derive(Deserialize)]
-#[serde(field_identifier, rename_all = "lowercase")]
+#[serde(field_identifier, rename_all = "snake_case")]
 pub enum LevelFields {
     Start,
     Objects,
+    Bounds,
+    KillHeight,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -83,6 +134,9 @@ pub enum LevelFields {
 pub enum MapObjectType {
     Box,
     Collectable,
+    Checkpoint,
+    Platform,
+    Finish,
 }
 
 struct LevelVisitor;
@@ -96,10 +150,7 @@ impl<'de> Visitor<'de> for LevelVisitor {
     where
         A: serde::de::MapAccess<'de>,
     {
-        let mut data = Level {
-            player_start: IVec2::ZERO,
-            objects: Vec::new(),
-        };
+        let mut data = Level::default();
         while let Some(key) = map.next_key::<LevelFields>()? {
             match key {
                 LevelFields::Start => {
@@ -108,6 +159,12 @@ impl<'de> Visitor<'de> for LevelVisitor {
                 LevelFields::Objects => {
                     data.objects = map.next_value_seed(ObjectsVisitor)?;
                 }
+                LevelFields::Bounds => {
+                    data.bounds = map.next_value()?;
+                }
+                LevelFields::KillHeight => {
+                    data.kill_height = map.next_value()?;
+                }
             }
         }
         Ok(data)
@@ -123,6 +180,9 @@ impl<'de> Visitor<'de> for LevelVisitor {
             objects: seq
                 .next_element_seed(ObjectsVisitor)?
                 .ok_or(serde::de::Error::missing_field("Objects"))?,
+            // a version 0 level ends before these
+            bounds: seq.next_element()?.flatten(),
+            kill_height: seq.next_element()?.flatten(),
         })
     }
 }
@@ -157,6 +217,15 @@ impl<'de> Visitor<'de> for ObjectsVisitor {
                 MapObjectType::Collectable => {
                     objects.push(Box::new(map.next_value::<Collectable>()?));
                 }
+                MapObjectType::Checkpoint => {
+                    objects.push(Box::new(map.next_value::<Checkpoint>()?));
+                }
+                MapObjectType::Platform => {
+                    objects.push(Box::new(map.next_value::<OneWayPlatform>()?));
+                }
+                MapObjectType::Finish => {
+                    objects.push(Box::new(map.next_value::<Finish>()?));
+                }
             }
         }
         Ok(objects)
@@ -193,9 +262,21 @@ impl Serialize for Level {
         S: serde::Serializer,
     {
         use ::serde::ser::SerializeStruct;
-        let mut struct_data = serializer.serialize_struct("Level", 2)?;
+        let human_readable = serializer.is_human_readable();
+        let mut struct_data = serializer.serialize_struct("Level", FIELDS.len())?;
         struct_data.serialize_field("start", &self.player_start)?;
         struct_data.serialize_field("objects", &ObjectsSerializer(&self.objects))?;
+        // bincode has no field names so it always needs every field
+        if human_readable && self.bounds.is_none() {
+            struct_data.skip_field("bounds")?;
+        } else {
+            struct_data.serialize_field("bounds", &self.bounds)?;
+        }
+        if human_readable && self.kill_height.is_none() {
+            struct_data.skip_field("kill_height")?;
+        } else {
+            struct_data.serialize_field("kill_height", &self.kill_height)?;
+        }
         struct_data.end()
     }
 }
@@ -239,6 +320,7 @@ fn test_serialize_level() {
                 spawn_type: SpawnType::Fixed(IVec2 { x: 5, y: 5 }),
             }),
         ],
+        ..Default::default()
     };
     assert_eq!(
         include_str!("test.lvl.ron"),
@@ -266,8 +348,25 @@ fn bincode_serde() {
     };
 
     let ser = level.to_base64().expect("To base64 to work");
-    assert_eq!(ser, "AAAAAgAUCAACAgABAAMKCg==");
+    assert_eq!(ser, "AQAAAgAUCAACAgABAAMKCgAA");
     println!("De = {};\nLen = {}\n", ser, ser.len());
     let de = Level::from_base64(&ser).expect("To Get level from str");
     assert!(level == de);
+    let old = Level::from_base64("AAAAAgAUCAACAgABAAMKCg==").expect("To read version 0");
+    assert!(level == old);
+
+    let bounded = Level {
+        bounds: Some(Bounds {
+            min: IVec2::new(-160, -64),
+            max: IVec2::new(160, 320),
+        }),
+        kill_height: Some(-32),
+        ..Default::default()
+    };
+    let de = Level::from_base64(&bounded.to_base64().expect("To base64 to work"))
+        .expect("To Get level from str");
+    assert!(bounded == de);
+    assert!(de.in_bounds(Vec2::ZERO));
+    assert!(!de.in_bounds(Vec2::new(0., -48.)));
+    assert!(!de.in_bounds(Vec2::new(200., 0.)));
 }
@@ -1,18 +1,22 @@
 use bevy::{
     prelude::{
         AddAsset, AssetServer, Assets, Bundle, Commands, Component, ComputedVisibility,
-        DespawnRecursiveExt, DetectChanges, Entity, EventWriter, GlobalTransform, Handle, Query,
-        Res, Resource, Transform, Visibility, With,
+        DespawnRecursiveExt, DetectChanges, Entity, EventWriter, GlobalTransform, Handle,
+        IntoSystemConfig, Query, Res, Resource, Transform, Visibility, With,
     },
     sprite::{TextureAtlas, TextureAtlasSprite},
 };
 use bevy_rapier2d::prelude::{Collider, RigidBody};
 
 pub use self::levels::Level;
+pub use self::platform::{platform_top, OneWayPlatform, OneWayPlatformHooks, ONE_WAY_GROUP};
 pub use self::tile_map::{spawn_map_objects, MapData, MapEvent, MapObject};
 
+mod checkpoint;
 mod collectable;
+mod finish;
 mod levels;
+mod platform;
 mod square;
 mod tile_map;
 pub struct MapPlugin;
@@ -22,12 +26,16 @@ impl bevy::prelude::Plugin for MapPlugin {
         app.add_startup_system(spawn_map)
             .add_event::<MapEvent>()
             .add_system(collectable::get_collectable)
+            .add_system(collectable::restore_collectables.in_set(LivesStages::Respawn))
+            .add_system(checkpoint::reach_checkpoint)
+            .add_system(finish::reach_finish)
             .add_system(spawn_map_objects)
             .init_resource::<MapData>()
             .add_asset::<Level>()
             .add_asset_loader(levels::LevelLoader)
             .init_resource::<LoadedLevel>()
-            .add_system(load_map);
+            .add_system(load_map)
+            .add_system(check_bounds.in_set(LivesStages::Kill));
     }
 }
 
@@ -51,7 +59,11 @@ struct CellBundle {
     pub map_item: MapItem,
 }
 
-use crate::{ghost::GhostEvents, player::RealPlayer};
+use crate::{
+    ghost::{Ghost, GhostEvents},
+    lives::{LivesStages, PlayerDied},
+    player::{start_position, RealPlayer, SpawnPoint},
+};
 
 #[derive(Resource, Default)]
 pub struct LoadedLevel(pub Handle<Level>);
@@ -66,16 +78,18 @@ fn load_map(
     map_item: Query<Entity, With<MapItem>>,
     mut commands: Commands,
     mut events: EventWriter<GhostEvents>,
-    mut player: Query<&mut Transform, With<RealPlayer>>,
+    mut players: Query<(&RealPlayer, &mut Transform, &mut SpawnPoint)>,
 ) {
     if !current_level.is_changed() {
         return;
     }
     let Some(level) = levels.get(&current_level.0) else {return;};
-    events.send(GhostEvents::ClearGhosts);
-    events.send(GhostEvents::ClearTrail);
-    let mut player = player.single_mut();
-    player.translation = level.player_start.as_vec2().extend(0.0);
+    for (&RealPlayer(slot), mut player, mut spawn) in &mut players {
+        events.send(GhostEvents::ClearGhosts(slot));
+        events.send(GhostEvents::ClearTrail(slot));
+        spawn.0 = start_position(level.player_start, slot);
+        player.translation = spawn.0;
+    }
     for item in &map_item {
         commands.entity(item).despawn_recursive();
     }
@@ -83,3 +97,25 @@ fn load_map(
         map_event.send(MapEvent::Spawn(MapObject::clone(obj.as_ref())))
     }
 }
+
+/// players outside the level die, ghosts outside it are gone for good
+fn check_bounds(
+    levels: Res<Assets<Level>>,
+    current_level: Res<LoadedLevel>,
+    players: Query<(Entity, &Transform), With<RealPlayer>>,
+    ghosts: Query<(Entity, &Transform), With<Ghost>>,
+    mut deaths: EventWriter<PlayerDied>,
+    mut commands: Commands,
+) {
+    let Some(level) = levels.get(&current_level.0) else {return;};
+    for (entity, pos) in &players {
+        if !level.in_bounds(pos.translation.truncate()) {
+            deaths.send(PlayerDied(entity));
+        }
+    }
+    for (entity, pos) in &ghosts {
+        if !level.in_bounds(pos.translation.truncate()) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{
+        BuildChildren, Commands, Component, Entity, IVec2, IVec3, Name, Query, SpatialBundle,
+        Transform, Vec2, Vec3,
+    },
+    reflect::Reflect,
+    sprite::TextureAtlasSprite,
+};
+use bevy_rapier2d::prelude::{
+    ActiveHooks, BevyPhysicsHooks, Collider, CollisionGroups, ContactModificationContextView,
+    Group, RigidBody,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    animation::{Animation, Animations},
+    player::DropThrough,
+};
+
+use super::{
+    tile_map::{MapData, MapObject, PlatformPiece, TerrainMaterial},
+    CellBundle,
+};
+
+/// A thin platform that can be jumped up through and stood on from above
+#[derive(Clone, Copy, Deserialize, Serialize, Reflect, Component)]
+pub struct OneWayPlatform {
+    /// the left most cell
+    pub offset: IVec3,
+    pub width: i32,
+    pub material: TerrainMaterial,
+}
+
+/// how far the top of the platform is above the middle of its cells
+const PLATFORM_TOP: f32 = 8.;
+const PLATFORM_THICKNESS: f32 = 5.;
+/// how far from straight up a contact can point and still be held up
+const ALLOWED_ANGLE: f32 = 0.1;
+/// the only group one-way platforms are in, so the kinematic controller can leave them out
+pub const ONE_WAY_GROUP: Group = Group::GROUP_3;
+
+/// height of the walkable top of the platform with this transform
+pub fn platform_top(transform: &Transform) -> f32 {
+    transform.translation.y + PLATFORM_THICKNESS / 2.
+}
+
+/// Lets contacts with a [`OneWayPlatform`] only push upwards,
+/// and not at all for bodies dropping through
+#[derive(SystemParam)]
+pub struct OneWayPlatformHooks<'w, 's> {
+    platforms: Query<'w, 's, &'static OneWayPlatform>,
+    dropping: Query<'w, 's, &'static DropThrough>,
+}
+
+impl BevyPhysicsHooks for OneWayPlatformHooks<'_, '_> {
+    fn modify_solver_contacts(&self, context: ContactModificationContextView) {
+        // the allowed normal is in the space of the first collider, pointing away from it
+        let (other, allowed_normal) = if self.platforms.contains(context.collider1()) {
+            (context.collider2(), Vec2::Y)
+        } else if self.platforms.contains(context.collider2()) {
+            (context.collider1(), Vec2::NEG_Y)
+        } else {
+            return;
+        };
+        if self
+            .dropping
+            .get(other)
+            .map_or(false, |drop| drop.is_dropping())
+        {
+            context.raw.solver_contacts.clear();
+            return;
+        }
+        context
+            .raw
+            .update_as_oneway_platform(&allowed_normal.into(), ALLOWED_ANGLE);
+    }
+}
+
+impl MapObject for OneWayPlatform {
+    fn spawn(
+        &self,
+        terrain: &Animations,
+        commands: &mut Commands,
+        map_data: &mut MapData,
+    ) -> Option<Entity> {
+        let width = self.width.max(1);
+        let center_x = (self.offset.x as f32 + (width as f32 / 2.)) * 16. - 8.;
+        let center_y = self.offset.y as f32 * 16. + PLATFORM_TOP - PLATFORM_THICKNESS / 2.;
+        let atlas = terrain
+            .get_atlas(Animation::Terrain)
+            .expect("Terrain is loaded");
+        Some(
+            commands
+                .spawn((
+                    CellBundle {
+                        transform: Transform::from_translation(Vec3::new(
+                            center_x,
+                            center_y,
+                            self.offset.z as f32,
+                        )),
+                        collider: Collider::cuboid(width as f32 * 8., PLATFORM_THICKNESS / 2.),
+                        rigid_body: RigidBody::Fixed,
+                        ..Default::default()
+                    },
+                    ActiveHooks::MODIFY_SOLVER_CONTACTS,
+                    CollisionGroups::new(ONE_WAY_GROUP, Group::ALL),
+                    Name::new("OneWayPlatform"),
+                    *self,
+                ))
+                .with_children(|p| {
+                    for x in 0..width {
+                        map_data.set_platform(IVec2::new(self.offset.x + x, self.offset.y));
+                        let piece = match (x, width) {
+                            (_, 1) => PlatformPiece::Middle,
+                            (0, _) => PlatformPiece::Left,
+                            (x, width) if x == width - 1 => PlatformPiece::Right,
+                            _ => PlatformPiece::Middle,
+                        };
+                        // the sprites are whole cells with the platform along their top
+                        let cell_x = (x as f32 - (width as f32 - 1.) / 2.) * 16.;
+                        let cell_y = self.offset.y as f32 * 16. - center_y;
+                        p.spawn((
+                            SpatialBundle {
+                                transform: Transform::from_translation(Vec3::new(
+                                    cell_x, cell_y, 0.,
+                                )),
+                                ..Default::default()
+                            },
+                            TextureAtlasSprite {
+                                index: self.material.to_platform_sprite(piece),
+                                ..Default::default()
+                            },
+                            atlas.clone(),
+                        ));
+                    }
+                })
+                .id(),
+        )
+    }
+    fn object_type(&self) -> super::levels::MapObjectType {
+        super::levels::MapObjectType::Platform
+    }
+    fn serialize(&self) -> bevy::reflect::serde::Serializable<'_> {
+        bevy::reflect::serde::Serializable::Borrowed(self)
+    }
+    fn clone(&self) -> Box<dyn MapObject> {
+        Box::new(<Self as Clone>::clone(self))
+    }
+    fn ui_draw(&self, _commands: &mut Commands, _root: Entity) {
+        todo!()
+    }
+}
@@ -43,6 +43,19 @@ pub enum TerrainType {
     OneUp = 47,
 }
 
+/// The three cells of the thin platforms in the top right of the terrain sheet
+#[derive(Clone, Copy)]
+pub enum PlatformPiece {
+    Left = 0,
+    Middle = 1,
+    Right = 2,
+}
+
+/// columns in the terrain sheet
+const TERRAIN_COLUMNS: usize = 22;
+/// column the thin platforms start in
+const PLATFORM_COLUMN: usize = 17;
+
 impl TerrainMaterial {
     pub fn to_sprite(self, terrain_type: TerrainType) -> usize {
         use TerrainMaterial::*;
@@ -50,6 +63,16 @@ impl TerrainMaterial {
             Gold | Clay | Copper | Iron | Brick => self as usize + terrain_type as usize,
         }
     }
+    /// the sheet only has gold, copper and iron platforms, the rest use the closest one
+    pub fn to_platform_sprite(self, piece: PlatformPiece) -> usize {
+        use TerrainMaterial::*;
+        let row = match self {
+            Gold => 0,
+            Copper | Brick | Clay => 1,
+            Iron => 2,
+        };
+        row * TERRAIN_COLUMNS + PLATFORM_COLUMN + piece as usize
+    }
 }
 
 pub trait MapObject: 'static + Send + Sync + std::any::Any + Reflect {
@@ -87,14 +110,21 @@ pub fn spawn_map_objects(
 #[derive(Default, Resource)]
 pub struct MapData {
     empty: HashSet<IVec2>,
+    /// cells with a one-way platform, kept apart so they never count as solid terrain
+    platforms: HashSet<IVec2>,
 }
 
 impl MapData {
+    /// neither terrain nor a platform is in `cell`
     pub fn is_empty(&self, cell: IVec2) -> bool {
-        !self.empty.contains(&cell)
+        !self.empty.contains(&cell) && !self.platforms.contains(&cell)
     }
 
     pub fn set_full(&mut self, cell: IVec2) {
         self.empty.insert(cell);
     }
+
+    pub fn set_platform(&mut self, cell: IVec2) {
+        self.platforms.insert(cell);
+    }
 }
@@ -2,18 +2,24 @@ use bevy::{
     prelude::{
         error, App, AssetServer, Assets, BuildChildren, Bundle, Button, ButtonBundle, Changed,
         ChildBuilder, Children, Color, Commands, Component, DespawnRecursiveExt, DetectChanges,
-        Entity, EventReader, FromWorld, Handle, IntoSystemAppConfig, IntoSystemAppConfigs,
-        IntoSystemConfig, IntoSystemConfigs, NextState, NodeBundle, OnEnter, OnExit, OnUpdate,
-        Plugin, Query, Res, ResMut, Resource, TextBundle, With, World,
+        Entity, EventReader, FromWorld, GamepadButton, Handle, Input, IntoSystemAppConfig,
+        IntoSystemAppConfigs, IntoSystemConfigs, KeyCode, NextState, NodeBundle, OnEnter, OnExit,
+        OnUpdate, Plugin, Query, Res, ResMut, Resource, TextBundle, With, World,
     },
     text::{BreakLineOn, Font, Text, TextAlignment, TextSection, TextStyle},
     ui::{FlexWrap, Interaction, Size, Style, UiRect, Val},
     window::ReceivedCharacter,
 };
 use bevy_editor_pls::editor::Editor;
+use leafwing_input_manager::{user_input::InputKind, Actionlike};
 
 use crate::{
+    characters::{Characters, Unlock},
+    controls::Controls,
     map::{Level, LoadedLevel},
+    player::{LocalPlayers, Player, MAX_LOCAL_PLAYERS},
+    roster::Roster,
+    user_input::PlayerInput,
     GameState,
 };
 
@@ -23,7 +29,7 @@ impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<MenuFont>()
             .add_system(setup_main_menu.in_schedule(OnEnter(GameState::Menu)))
-            .add_system(state_buttons.in_set(OnUpdate(GameState::Menu)))
+            .add_systems((state_buttons, local_players_button).in_set(OnUpdate(GameState::Menu)))
             .add_system(cleanup_menu.in_schedule(OnExit(GameState::Menu)))
             .add_systems(
                 (
@@ -63,6 +69,26 @@ impl Plugin for MenuPlugin {
                     .chain()
                     .in_schedule(OnExit(GameState::InputLevelName)),
             )
+            .add_system(setup_character_select.in_schedule(OnEnter(GameState::CharacterSelect)))
+            .add_systems(
+                (select_character, state_buttons).in_set(OnUpdate(GameState::CharacterSelect)),
+            )
+            .add_system(cleanup_menu.in_schedule(OnExit(GameState::CharacterSelect)))
+            .init_resource::<RebindTarget>()
+            .add_system(setup_controls.in_schedule(OnEnter(GameState::Controls)))
+            .add_systems(
+                (
+                    state_buttons,
+                    control_buttons,
+                    capture_rebind,
+                    update_control_text,
+                )
+                    .in_set(OnUpdate(GameState::Controls)),
+            )
+            .add_systems(
+                (cleanup_menu, |controls: Res<Controls>| controls.save())
+                    .in_schedule(OnExit(GameState::Controls)),
+            )
             .insert_resource(LevelString(String::new()));
     }
 }
@@ -114,7 +140,7 @@ fn make_button<T: Bundle>(
         });
 }
 
-fn setup_main_menu(mut commands: Commands, font: Res<MenuFont>) {
+fn setup_main_menu(mut commands: Commands, font: Res<MenuFont>, local_players: Res<LocalPlayers>) {
     commands
         .spawn((
             NodeBundle {
@@ -132,11 +158,18 @@ fn setup_main_menu(mut commands: Commands, font: Res<MenuFont>) {
         .with_children(|p| {
             let style = Style {
                 padding: UiRect::top(Val::Px(10.)),
-                size: Size::new(Val::Percent(100.), Val::Percent(20.)),
+                size: Size::new(Val::Percent(100.), Val::Percent(14.)),
                 ..Default::default()
             };
             Size::new(Val::Percent(100.), Val::Percent(20.));
             make_button(p, style.clone(), "Play", font.0.clone(), GameState::Play);
+            make_button(
+                p,
+                style.clone(),
+                &players_text(local_players.0),
+                font.0.clone(),
+                LocalPlayersButton,
+            );
             make_button(
                 p,
                 style.clone(),
@@ -151,10 +184,258 @@ fn setup_main_menu(mut commands: Commands, font: Res<MenuFont>) {
                 font.0.clone(),
                 GameState::InputLevelName,
             );
+            make_button(
+                p,
+                style.clone(),
+                "Characters",
+                font.0.clone(),
+                GameState::CharacterSelect,
+            );
+            make_button(
+                p,
+                style.clone(),
+                "Controls",
+                font.0.clone(),
+                GameState::Controls,
+            );
             make_button(p, style, "Editor", font.0.clone(), GameState::LevelEditor);
         });
 }
 
+#[derive(Component)]
+struct LocalPlayersButton;
+
+fn players_text(count: usize) -> String {
+    format!("Players: {}", count)
+}
+
+/// each click adds a local player, going back to one after the last
+fn local_players_button(
+    query: Query<(&Interaction, &Children), (With<LocalPlayersButton>, Changed<Interaction>)>,
+    mut text: Query<&mut Text>,
+    mut local_players: ResMut<LocalPlayers>,
+) {
+    for (interaction, children) in &query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        local_players.0 = local_players.0 % MAX_LOCAL_PLAYERS + 1;
+        for child in children {
+            if let Ok(mut text) = text.get_mut(*child) {
+                text.sections[0].value = players_text(local_players.0);
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct SelectCharacter(Player);
+
+fn setup_character_select(
+    mut commands: Commands,
+    font: Res<MenuFont>,
+    characters: Res<Characters>,
+    roster: Res<Roster>,
+) {
+    let selected = roster.selected(&characters).unwrap_or_default();
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    margin: UiRect::all(Val::Auto),
+                    size: Size::new(Val::Percent(25.), Val::Percent(66.)),
+                    flex_wrap: FlexWrap::Wrap,
+                    ..Default::default()
+                },
+                background_color: Color::GRAY.into(),
+                ..Default::default()
+            },
+            MenuItem,
+        ))
+        .with_children(|p| {
+            let style = Style {
+                padding: UiRect::top(Val::Px(10.)),
+                size: Size::new(Val::Percent(100.), Val::Percent(15.)),
+                ..Default::default()
+            };
+            for (player, character) in characters.iter() {
+                let text = if !roster.is_unlocked(character) {
+                    match &character.unlock {
+                        Unlock::Score(score) => format!("{} (score {})", character.name, score),
+                        Unlock::Level(level) => format!("{} (finish {})", character.name, level),
+                        Unlock::Always => character.name.clone(),
+                    }
+                } else if player == selected {
+                    format!("> {} <", character.name)
+                } else {
+                    character.name.clone()
+                };
+                make_button(
+                    p,
+                    style.clone(),
+                    &text,
+                    font.0.clone(),
+                    SelectCharacter(player),
+                );
+            }
+            make_button(p, style, "Back", font.0.clone(), GameState::Menu);
+        });
+}
+
+fn select_character(
+    query: Query<(&Interaction, &SelectCharacter), (With<Button>, Changed<Interaction>)>,
+    mut roster: ResMut<Roster>,
+    characters: Res<Characters>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, SelectCharacter(player)) in &query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let Some(character) = characters.get(*player) else {continue;};
+        if !roster.is_unlocked(character) {
+            continue;
+        }
+        roster.selected = Some(character.name.clone());
+        roster.save();
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+/// The slot shown on the controls screen and the action waiting for a new binding
+#[derive(Resource, Default)]
+struct RebindTarget {
+    slot: usize,
+    action: Option<PlayerInput>,
+}
+
+#[derive(Component)]
+enum ControlButton {
+    Slot,
+    Action(PlayerInput),
+    Reset,
+}
+
+fn setup_controls(mut commands: Commands, font: Res<MenuFont>, mut target: ResMut<RebindTarget>) {
+    *target = RebindTarget::default();
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    margin: UiRect::all(Val::Auto),
+                    size: Size::new(Val::Percent(50.), Val::Percent(80.)),
+                    flex_wrap: FlexWrap::Wrap,
+                    ..Default::default()
+                },
+                background_color: Color::GRAY.into(),
+                ..Default::default()
+            },
+            MenuItem,
+        ))
+        .with_children(|p| {
+            let style = Style {
+                padding: UiRect::top(Val::Px(10.)),
+                size: Size::new(Val::Percent(100.), Val::Percent(10.)),
+                ..Default::default()
+            };
+            // the text is filled in by `update_control_text`
+            make_button(p, style.clone(), "", font.0.clone(), ControlButton::Slot);
+            for action in PlayerInput::variants() {
+                make_button(
+                    p,
+                    style.clone(),
+                    "",
+                    font.0.clone(),
+                    ControlButton::Action(action),
+                );
+            }
+            make_button(
+                p,
+                style.clone(),
+                "Reset",
+                font.0.clone(),
+                ControlButton::Reset,
+            );
+            make_button(p, style, "Back", font.0.clone(), GameState::Menu);
+        });
+}
+
+fn control_buttons(
+    query: Query<(&Interaction, &ControlButton), (With<Button>, Changed<Interaction>)>,
+    mut target: ResMut<RebindTarget>,
+    mut controls: ResMut<Controls>,
+) {
+    for (interaction, button) in &query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match button {
+            ControlButton::Slot => {
+                target.slot = (target.slot + 1) % MAX_LOCAL_PLAYERS;
+                target.action = None;
+            }
+            ControlButton::Action(action) => target.action = Some(action.clone()),
+            ControlButton::Reset => {
+                controls.reset(target.slot);
+                target.action = None;
+            }
+        }
+    }
+}
+
+/// the next key or gamepad button pressed becomes the binding, escape cancels
+fn capture_rebind(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut target: ResMut<RebindTarget>,
+    mut controls: ResMut<Controls>,
+) {
+    let Some(action) = target.action.clone() else {return;};
+    if keys.just_pressed(KeyCode::Escape) {
+        target.action = None;
+        return;
+    }
+    let input = if let Some(key) = keys.get_just_pressed().next() {
+        InputKind::Keyboard(*key)
+    } else if let Some(button) = buttons.get_just_pressed().next() {
+        InputKind::GamepadButton(button.button_type)
+    } else {
+        return;
+    };
+    controls.rebind(target.slot, action, input);
+    target.action = None;
+}
+
+fn update_control_text(
+    query: Query<(&ControlButton, &Children)>,
+    mut text: Query<&mut Text>,
+    target: Res<RebindTarget>,
+    controls: Res<Controls>,
+) {
+    if !target.is_changed() && !controls.is_changed() {
+        return;
+    }
+    for (button, children) in &query {
+        let value = match button {
+            ControlButton::Slot => format!("Player {}", target.slot + 1),
+            ControlButton::Action(action) if target.action.as_ref() == Some(action) => {
+                format!("{:?}: press a key or button", action)
+            }
+            ControlButton::Action(action) => format!(
+                "{:?}: {}",
+                action,
+                controls.describe(target.slot, action.clone())
+            ),
+            ControlButton::Reset => continue,
+        };
+        for child in children {
+            if let Ok(mut text) = text.get_mut(*child) {
+                text.sections[0].value = value.clone();
+            }
+        }
+    }
+}
+
 fn setup_level_select(
     mut commands: Commands,
     font: Res<MenuFont>,
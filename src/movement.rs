@@ -0,0 +1,218 @@
+use bevy::{
+    asset::{AssetLoader, LoadedAsset},
+    prelude::{
+        AddAsset, App, AssetEvent, AssetServer, Assets, Commands, DetectChanges, EventReader,
+        Handle, Plugin, Query, Res, ResMut, Resource, Vec2, With,
+    },
+    reflect::{Reflect, TypeUuid},
+};
+use bevy_rapier2d::prelude::{Damping, Friction, RapierConfiguration};
+use serde::{Deserialize, Serialize};
+
+use crate::{controller::ControllerMode, player::Player};
+
+const CONFIG_PATH: &str = "player.movement.ron";
+
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<MovementConfig>()
+            .init_asset_loader::<MovementConfigLoader>()
+            .init_resource::<MovementConfig>()
+            .register_type::<MovementConfig>()
+            .add_startup_system(load_movement_config)
+            .add_system(update_movement_config)
+            .add_system(apply_movement_config);
+    }
+}
+
+/// Everything that decides how the player moves, loaded from `player.movement.ron`
+#[derive(Debug, Clone, Resource, Reflect, TypeUuid, Deserialize, Serialize)]
+#[uuid = "8d5b0c55-3f7e-4b8e-a1a2-6c0f3e9b7d21"]
+pub struct MovementConfig {
+    /// downward pull in pixels per second squared, negative is down
+    pub gravity: f32,
+    pub max_speed: f32,
+    /// speed added every frame left or right is held
+    pub acceleration: f32,
+    pub friction: f32,
+    pub linear_damping: f32,
+    /// tallest ledge `auto_step` walks up
+    pub step_height: f32,
+    pub controller: ControllerMode,
+    /// steepest slope in degrees the kinematic controller walks up
+    pub max_slope_angle: f32,
+    /// how far down the kinematic controller sticks to the ground when walking off a slope
+    pub snap_to_ground: f32,
+    pub jump: JumpConfig,
+    pub dash: DashConfig,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        MovementConfig {
+            gravity: -294.,
+            max_speed: 200.,
+            acceleration: 50.,
+            friction: 5.,
+            linear_damping: 1.,
+            step_height: 15.9,
+            controller: ControllerMode::Dynamic,
+            max_slope_angle: 45.,
+            snap_to_ground: 4.,
+            jump: JumpConfig::default(),
+            dash: DashConfig::default(),
+        }
+    }
+}
+
+/// How high and how forgiving jumping is
+#[derive(Debug, Clone, Reflect, Deserialize, Serialize)]
+pub struct JumpConfig {
+    /// seconds after walking off a ledge a jump still counts
+    pub coyote_time: f32,
+    /// seconds before landing a jump press is remembered
+    pub buffer_time: f32,
+    /// pixels a tapped jump rises
+    pub min_height: f32,
+    /// pixels a held jump rises
+    pub max_height: f32,
+    /// fastest fall while pressing into a wall
+    pub wall_slide_speed: f32,
+    /// sideways speed a wall jump leaves the wall with
+    pub wall_jump_push: f32,
+    /// seconds after a wall jump that steering back towards the wall is ignored
+    pub wall_jump_lock: f32,
+    /// seconds one-way platforms are passed through after dropping off one
+    pub drop_through_time: f32,
+}
+
+impl Default for JumpConfig {
+    fn default() -> Self {
+        JumpConfig {
+            coyote_time: 0.1,
+            buffer_time: 0.1,
+            min_height: 32.,
+            max_height: 106.,
+            wall_slide_speed: 60.,
+            wall_jump_push: 150.,
+            wall_jump_lock: 0.2,
+            drop_through_time: 0.25,
+        }
+    }
+}
+
+impl JumpConfig {
+    /// the upward speed that reaches `height` under `gravity`
+    pub fn velocity(height: f32, gravity: f32) -> f32 {
+        (2. * gravity.abs() * height.max(0.)).sqrt()
+    }
+    /// the speed to jump with, a jump that is no longer held only gets the minimum height
+    pub fn jump_velocity(&self, held: bool, gravity: f32) -> f32 {
+        let height = if held {
+            self.max_height.max(self.min_height)
+        } else {
+            self.min_height
+        };
+        JumpConfig::velocity(height, gravity)
+    }
+}
+
+/// The burst of speed `PlayerInput::Dash` gives
+#[derive(Debug, Clone, Reflect, Deserialize, Serialize)]
+pub struct DashConfig {
+    pub speed: f32,
+    /// seconds the dash lasts
+    pub duration: f32,
+    /// seconds from the start of one dash to when the next can start
+    pub cooldown: f32,
+    /// hold the height while dashing instead of falling
+    pub suspend_gravity: bool,
+    /// seconds from the start of a dash that ghosts can't kill the player
+    pub immunity: f32,
+}
+
+impl Default for DashConfig {
+    fn default() -> Self {
+        DashConfig {
+            speed: 400.,
+            duration: 0.15,
+            cooldown: 0.6,
+            suspend_gravity: true,
+            immunity: 0.25,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct MovementConfigHandle(Handle<MovementConfig>);
+
+fn load_movement_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(MovementConfigHandle(asset_server.load(CONFIG_PATH)));
+}
+
+/// copies the asset into the resource whenever it is loaded or edited
+fn update_movement_config(
+    mut events: EventReader<AssetEvent<MovementConfig>>,
+    handle: Res<MovementConfigHandle>,
+    assets: Res<Assets<MovementConfig>>,
+    mut config: ResMut<MovementConfig>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed }
+                if *changed == handle.0 =>
+            {
+                let Some(new_config) = assets.get(changed) else {continue;};
+                *config = new_config.clone();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// pushes the parts of the config that live on components or in rapier
+fn apply_movement_config(
+    config: Res<MovementConfig>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut bodies: Query<(&mut Friction, &mut Damping), With<Player>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    rapier_config.gravity = Vec2::Y * config.gravity;
+    for (mut friction, mut damping) in &mut bodies {
+        friction.coefficient = config.friction;
+        damping.linear_damping = config.linear_damping;
+    }
+}
+
+#[derive(Default)]
+pub struct MovementConfigLoader;
+
+impl AssetLoader for MovementConfigLoader {
+    fn extensions(&self) -> &[&str] {
+        &["movement.ron"]
+    }
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config: MovementConfig = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+}
+
+#[test]
+fn movement_file() {
+    let config: MovementConfig = ron::de::from_str(include_str!("../assets/player.movement.ron"))
+        .expect("valid movement config");
+    // the jump the game shipped with before it was tunable
+    let velocity = JumpConfig::velocity(config.jump.max_height, config.gravity);
+    assert!((velocity - 250.).abs() < 1.);
+}
@@ -1,19 +1,29 @@
 use bevy::{
     prelude::{
-        default, error, App, Changed, Commands, Component, Entity, IntoSystemConfig, Name, Plugin,
-        Query, Res, SpriteSheetBundle, SystemSet, TextureAtlasSprite, Transform, Vec2,
+        default, error, App, Assets, Changed, Commands, Component, DespawnRecursiveExt,
+        DetectChanges, Entity, IVec2, IntoSystemConfig, Name, Plugin, Query, Res, Resource,
+        SpriteSheetBundle, SystemSet, TextureAtlasSprite, Time, Transform, Vec2, Vec3, With,
+        Without,
     },
     reflect::Reflect,
 };
 use bevy_rapier2d::prelude::{
-    CoefficientCombineRule, Collider, Damping, Friction, LockedAxes, QueryFilter, RapierContext,
-    RigidBody, Velocity,
+    CoefficientCombineRule, Collider, Damping, Friction, KinematicCharacterController, LockedAxes,
+    QueryFilter, RapierContext, RigidBody, Velocity,
 };
 use leafwing_input_manager::{prelude::ActionState, InputManagerBundle};
 
 use crate::{
-    animation::{Animation, Animations},
+    animation::{Animation, AnimationSpeed, Animations, CharacterAnimation, Facing},
+    animation_graph::{AnimationClips, AnimationStateMachine},
+    characters::Characters,
+    controls::Controls,
+    lives::Lives,
+    map::{Level, LoadedLevel, OneWayPlatform},
+    movement::{JumpConfig, MovementConfig},
+    roster::Roster,
     user_input::PlayerInput,
+    Score,
 };
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, SystemSet)]
@@ -25,81 +35,269 @@ pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_player)
+        app.init_resource::<LocalPlayers>()
+            .add_system(spawn_players)
             .add_system(move_player.in_set(PlayerStages::Move))
             .add_system(ground_detection)
+            .add_system(
+                update_jump_timers
+                    .in_set(PlayerStages::Move)
+                    .before(dubble_jump)
+                    .after(ground_detection),
+            )
+            .add_system(
+                wall_slide
+                    .in_set(PlayerStages::Move)
+                    .after(update_jump_timers)
+                    .before(dubble_jump),
+            )
             .add_system(dubble_jump.in_set(PlayerStages::Move).before(move_player))
+            .add_system(
+                drop_through
+                    .in_set(PlayerStages::Move)
+                    .after(update_jump_timers)
+                    .before(wall_slide)
+                    .before(dubble_jump)
+                    .before(move_player),
+            )
+            .add_system(cut_jump.in_set(PlayerStages::Move).after(move_player))
+            .add_system(glide.in_set(PlayerStages::Move).after(move_player))
             .add_system(change_player.in_set(PlayerStages::Move))
+            .add_system(update_facing.in_set(PlayerStages::Move))
+            .add_system(
+                dash.in_set(PlayerStages::Move)
+                    .after(move_player)
+                    .after(update_facing),
+            )
+            .add_system(apply_dash.in_set(PlayerStages::Move).after(dash))
             .add_system(auto_step.in_set(PlayerStages::Move).before(move_player))
+            .add_system(scale_run_animation)
             .register_type::<Grounded>()
             .register_type::<Jump>()
+            .register_type::<JumpTimers>()
+            .register_type::<OnWall>()
+            .register_type::<AirJumps>()
+            .register_type::<Dash>()
+            .register_type::<DropThrough>()
             .register_type::<Player>();
     }
 }
 
-#[derive(Component, Reflect, PartialEq, Clone, Copy)]
-pub enum Player {
-    Mask,
-    Ninja,
-    Pink,
-    Guy,
+/// Index of the character in `characters.ron`
+#[derive(Component, Reflect, Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Player(pub usize);
+
+/// A player controlled by someone, the slot picks their input map
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct RealPlayer(pub usize);
+
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+
+/// How many real players are playing on this machine
+#[derive(Resource)]
+pub struct LocalPlayers(pub usize);
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        LocalPlayers(1)
+    }
 }
 
-#[derive(Component)]
-pub struct RealPlayer;
+/// Where the player goes back to when they die
+#[derive(Component, Default)]
+pub struct SpawnPoint(pub Vec3);
+
+/// players stand side by side at the start of a level
+pub fn start_position(player_start: IVec2, slot: usize) -> Vec3 {
+    player_start.as_vec2().extend(0.0) + Vec3::X * 24. * slot as f32
+}
 
-fn spawn_player(mut commands: Commands, animations: Res<Animations>) {
-    let Some(handle) = animations.get_animation(Animation::MaskIdle) else {error!("Failed to find animation: Idle"); return;};
-    commands.spawn((
-        SpriteSheetBundle {
-            texture_atlas: default(),
-            sprite: TextureAtlasSprite {
-                index: 0,
+/// spawns or despawns real players until there is one for each slot
+fn spawn_players(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    players: Query<(Entity, &RealPlayer)>,
+    animations: Res<Animations>,
+    config: Res<MovementConfig>,
+    characters: Res<Characters>,
+    roster: Res<Roster>,
+    controls: Res<Controls>,
+    levels: Res<Assets<Level>>,
+    loaded_level: Res<LoadedLevel>,
+) {
+    if !local_players.is_changed() {
+        return;
+    }
+    let mut taken = [false; MAX_LOCAL_PLAYERS];
+    for (entity, &RealPlayer(slot)) in &players {
+        if slot < local_players.0 {
+            taken[slot] = true;
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    let player_start = levels
+        .get(&loaded_level.0)
+        .map_or(IVec2::ZERO, |level| level.player_start);
+    for slot in 0..local_players.0.min(MAX_LOCAL_PLAYERS) {
+        if taken[slot] {
+            continue;
+        }
+        let player = roster.starting_character(&characters, slot);
+        let Some(handle) = animations.get_animation(Animation::Character(player, CharacterAnimation::Idle)) else {error!("Failed to find animation: Idle"); return;};
+        let position = start_position(player_start, slot);
+        commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: default(),
+                sprite: TextureAtlasSprite {
+                    index: 0,
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(position),
+                ..Default::default()
+            },
+            player,
+            (
+                RealPlayer(slot),
+                Score::default(),
+                Lives::default(),
+                SpawnPoint(position),
+            ),
+            (
+                handle,
+                AnimationStateMachine::new(animations.character_graph()),
+                AnimationClips::default(),
+                AnimationSpeed::default(),
+                Facing::default(),
+            ),
+            Grounded(true),
+            GroundContact::default(),
+            InputManagerBundle {
+                input_map: controls.for_slot(slot),
                 ..Default::default()
             },
-            ..Default::default()
-        },
-        Player::Mask,
-        RealPlayer,
-        handle,
-        Grounded(true),
-        GroundedCheck(0.0, 0),
-        InputManagerBundle {
-            input_map: PlayerInput::player_one(),
-            ..Default::default()
-        },
-        Jump(false),
-        RigidBody::Dynamic,
-        Velocity::default(),
-        Collider::cuboid(9., 15.95),
-        LockedAxes::ROTATION_LOCKED_Z,
-        Friction {
-            coefficient: 5.,
-            combine_rule: CoefficientCombineRule::Multiply,
-        },
-        Damping {
-            linear_damping: 1.,
-            angular_damping: 1.,
-        },
-        Name::new("Player"),
-    ));
+            (
+                Jump(false),
+                AirJumps::default(),
+                JumpTimers::default(),
+                OnWall::default(),
+                Dash::default(),
+                DropThrough::default(),
+            ),
+            RigidBody::Dynamic,
+            Velocity::default(),
+            Collider::cuboid(9., 15.95),
+            LockedAxes::ROTATION_LOCKED_Z,
+            Friction {
+                coefficient: config.friction,
+                combine_rule: CoefficientCombineRule::Multiply,
+            },
+            Damping {
+                linear_damping: config.linear_damping,
+                angular_damping: 1.,
+            },
+            Name::new(format!("Player {}", slot + 1)),
+        ));
+    }
+}
+
+/// slowest the run cycle plays at when barely moving
+const MIN_RUN_ANIMATION_SPEED: f32 = 0.25;
+
+/// plays the run cycle in step with how fast the player is moving
+fn scale_run_animation(
+    mut query: Query<(&AnimationStateMachine, &Velocity, &mut AnimationSpeed), With<Player>>,
+    config: Res<MovementConfig>,
+) {
+    for (machine, velocity, mut speed) in &mut query {
+        let new_speed = if machine.state.as_deref() == Some(CharacterAnimation::Run.name()) {
+            (velocity.linvel.x.abs() / config.max_speed).clamp(MIN_RUN_ANIMATION_SPEED, 1.)
+        } else {
+            1.
+        };
+        if speed.0 != new_speed {
+            speed.0 = new_speed;
+        }
+    }
+}
+
+/// Seconds since the last time on the ground, since jump was last pressed
+/// and since the last wall jump
+#[derive(Component, Reflect)]
+pub struct JumpTimers {
+    pub air_time: f32,
+    pub since_pressed: f32,
+    pub since_wall_jump: f32,
+}
+
+impl Default for JumpTimers {
+    fn default() -> Self {
+        JumpTimers {
+            air_time: f32::INFINITY,
+            since_pressed: f32::INFINITY,
+            since_wall_jump: f32::INFINITY,
+        }
+    }
 }
 
-pub const MAX_SPEED: f32 = 200.;
-pub const ACCELERATION: f32 = 50.;
+impl JumpTimers {
+    /// on the ground or only just left it without jumping
+    pub fn can_jump(&self, config: &JumpConfig) -> bool {
+        self.air_time <= config.coyote_time
+    }
+    pub fn buffered(&self, config: &JumpConfig) -> bool {
+        self.since_pressed <= config.buffer_time
+    }
+    /// uses up the press and the coyote time so one press is one jump
+    pub fn consume(&mut self) {
+        self.air_time = f32::INFINITY;
+        self.since_pressed = f32::INFINITY;
+    }
+}
+
+fn update_jump_timers(
+    mut query: Query<(
+        &mut JumpTimers,
+        &Grounded,
+        &Velocity,
+        &ActionState<PlayerInput>,
+    )>,
+    time: Res<Time>,
+) {
+    for (mut timers, grounded, velocity, input) in &mut query {
+        // `Grounded` lags behind a jump by a few frames, rising is never standing
+        if grounded.0 && velocity.linvel.y <= 0.01 {
+            timers.air_time = 0.;
+        } else {
+            timers.air_time += time.delta_seconds();
+        }
+        if input.just_pressed(PlayerInput::Jump) {
+            timers.since_pressed = 0.;
+        } else {
+            timers.since_pressed += time.delta_seconds();
+        }
+        timers.since_wall_jump += time.delta_seconds();
+    }
+}
 
 fn move_player(
     mut player: Query<(
         &mut Velocity,
         &ActionState<PlayerInput>,
-        &Grounded,
+        &mut JumpTimers,
         &Transform,
     )>,
     rapier_context: Res<RapierContext>,
+    config: Res<MovementConfig>,
 ) {
-    for (mut velocity, input, grounded, pos) in &mut player {
-        if input.just_pressed(PlayerInput::Jump) & grounded {
-            velocity.linvel.y = 250.;
+    for (mut velocity, input, mut timers, pos) in &mut player {
+        // right after a wall jump holding towards the wall would cancel the push
+        let locked = timers.since_wall_jump <= config.jump.wall_jump_lock;
+        if timers.buffered(&config.jump) && timers.can_jump(&config.jump) {
+            timers.consume();
+            velocity.linvel.y = config
+                .jump
+                .jump_velocity(input.pressed(PlayerInput::Jump), config.gravity);
         } else if input.just_pressed(PlayerInput::Fall) {
             velocity.linvel.y = velocity.linvel.y.min(0.0);
         } else if input.pressed(PlayerInput::Left) {
@@ -110,8 +308,8 @@ fn move_player(
                 false,
                 QueryFilter::exclude_dynamic().exclude_sensors(),
             );
-            if hit.is_none() {
-                velocity.linvel.x -= ACCELERATION;
+            if hit.is_none() && !(locked && velocity.linvel.x > 0.) {
+                velocity.linvel.x -= config.acceleration;
             }
         } else if input.pressed(PlayerInput::Right) {
             let hit = rapier_context.cast_ray(
@@ -121,52 +319,290 @@ fn move_player(
                 false,
                 QueryFilter::exclude_dynamic().exclude_sensors(),
             );
-            if hit.is_none() {
-                velocity.linvel.x += ACCELERATION;
+            if hit.is_none() && !(locked && velocity.linvel.x < 0.) {
+                velocity.linvel.x += config.acceleration;
             }
         };
-        velocity.linvel.x = velocity.linvel.x.clamp(-MAX_SPEED, MAX_SPEED);
+        velocity.linvel.x = velocity.linvel.x.clamp(-config.max_speed, config.max_speed);
+    }
+}
+
+/// turns the player the way they are trying to move, so bumping into things does not turn them
+fn update_facing(mut query: Query<(&mut Facing, &ActionState<PlayerInput>)>) {
+    for (mut facing, input) in &mut query {
+        let new_facing = match (
+            input.pressed(PlayerInput::Left),
+            input.pressed(PlayerInput::Right),
+        ) {
+            (true, false) => Facing::Left,
+            (false, true) => Facing::Right,
+            _ => continue,
+        };
+        if *facing != new_facing {
+            *facing = new_facing;
+        }
+    }
+}
+
+/// how far from the middle of the player a wall can be slid on
+const WALL_DISTANCE: f32 = 11.;
+
+/// Side the wall being slid down is on, `None` when not on a wall
+#[derive(Component, Reflect, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OnWall(pub Option<Facing>);
+
+fn wall_slide(
+    mut player: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Velocity,
+            &mut OnWall,
+            &mut Jump,
+            &mut AirJumps,
+            &mut JumpTimers,
+            &mut Facing,
+            &Grounded,
+            &Player,
+            &ActionState<PlayerInput>,
+        ),
+        With<RealPlayer>,
+    >,
+    rapier_context: Res<RapierContext>,
+    config: Res<MovementConfig>,
+    characters: Res<Characters>,
+) {
+    for (
+        entity,
+        pos,
+        mut velocity,
+        mut on_wall,
+        mut jump,
+        mut air_jumps,
+        mut timers,
+        mut facing,
+        grounded,
+        player,
+        input,
+    ) in &mut player
+    {
+        let abilities = characters.abilities(*player);
+        let pressing = match (
+            input.pressed(PlayerInput::Left),
+            input.pressed(PlayerInput::Right),
+        ) {
+            (true, false) => Some(Facing::Left),
+            (false, true) => Some(Facing::Right),
+            _ => None,
+        };
+        let wall = pressing.filter(|side| {
+            abilities.wall_jump
+                && !grounded.0
+                && rapier_context
+                    .cast_ray(
+                        pos.translation.truncate(),
+                        Vec2::X * side.sign(),
+                        WALL_DISTANCE,
+                        true,
+                        QueryFilter::exclude_dynamic()
+                            .exclude_sensors()
+                            .exclude_rigid_body(entity),
+                    )
+                    .is_some()
+        });
+        if on_wall.0 != wall {
+            on_wall.0 = wall;
+        }
+        let Some(side) = wall else {continue;};
+        if timers.buffered(&config.jump) {
+            timers.consume();
+            timers.since_wall_jump = 0.;
+            air_jumps.0 = 0;
+            jump.0 = abilities.air_jumps > 0;
+            on_wall.0 = None;
+            *facing = side.opposite();
+            velocity.linvel = Vec2::new(
+                facing.sign() * config.jump.wall_jump_push,
+                config
+                    .jump
+                    .jump_velocity(input.pressed(PlayerInput::Jump), config.gravity),
+            );
+        } else {
+            velocity.linvel.y = velocity.linvel.y.max(-config.jump.wall_slide_speed);
+        }
     }
 }
 
+/// Jumps made in the air since last on the ground
+#[derive(Component, Reflect, Default)]
+pub struct AirJumps(pub usize);
+
 fn dubble_jump(
-    mut player: Query<(&mut Jump, &mut Velocity, &ActionState<PlayerInput>)>,
+    mut player: Query<(
+        &mut Jump,
+        &mut AirJumps,
+        &mut Velocity,
+        &ActionState<PlayerInput>,
+        &mut JumpTimers,
+        &Player,
+    )>,
     can_jump: Query<(Entity, &Grounded), Changed<Grounded>>,
+    config: Res<MovementConfig>,
+    characters: Res<Characters>,
 ) {
     for (entity, grounded) in &can_jump {
-        if let Ok((mut jump, _, _)) = player.get_mut(entity) {
+        if let Ok((mut jump, mut air_jumps, _, _, _, player)) = player.get_mut(entity) {
             if grounded.0 {
-                jump.0 = true;
+                air_jumps.0 = 0;
+                jump.0 = characters.abilities(*player).air_jumps > 0;
             }
         }
     }
-    for (mut jump, mut velocity, input) in player.iter_mut() {
+    for (mut jump, mut air_jumps, mut velocity, input, mut timers, player) in player.iter_mut() {
         if velocity.linvel.y.abs() < 0.01 {
-            return;
+            continue;
+        }
+        // inside the coyote time this press is the normal jump
+        if timers.can_jump(&config.jump) {
+            continue;
+        }
+        // a press already used up by a wall jump is not also a double jump
+        if input.just_pressed(PlayerInput::Jump) && timers.buffered(&config.jump) && jump.0 {
+            air_jumps.0 += 1;
+            jump.0 = air_jumps.0 < characters.abilities(*player).air_jumps;
+            timers.consume();
+            velocity.linvel.y = config.jump.jump_velocity(true, config.gravity);
+        }
+    }
+}
+
+/// holding jump while falling slows the fall for characters that can glide
+fn glide(
+    mut player: Query<(&mut Velocity, &Grounded, &Player, &ActionState<PlayerInput>)>,
+    characters: Res<Characters>,
+) {
+    for (mut velocity, grounded, player, input) in &mut player {
+        let Some(glide_speed) = characters.abilities(*player).glide else {continue;};
+        if !grounded.0 && input.pressed(PlayerInput::Jump) && velocity.linvel.y < -glide_speed {
+            velocity.linvel.y = -glide_speed;
+        }
+    }
+}
+
+/// Seconds left of the current dash, its cooldown and its immunity
+#[derive(Component, Reflect, Default, Clone, Copy)]
+pub struct Dash {
+    pub active: f32,
+    pub cooldown: f32,
+    pub immunity: f32,
+    /// already dashed since leaving the ground
+    pub air_dashed: bool,
+}
+
+impl Dash {
+    pub fn is_dashing(&self) -> bool {
+        self.active > 0.
+    }
+    pub fn is_immune(&self) -> bool {
+        self.immunity > 0.
+    }
+}
+
+fn dash(
+    mut player: Query<(&mut Dash, &Grounded, &Player, &ActionState<PlayerInput>)>,
+    config: Res<MovementConfig>,
+    characters: Res<Characters>,
+    time: Res<Time>,
+) {
+    for (mut dash, grounded, player, input) in &mut player {
+        let delta = time.delta_seconds();
+        dash.active = (dash.active - delta).max(0.);
+        dash.cooldown = (dash.cooldown - delta).max(0.);
+        dash.immunity = (dash.immunity - delta).max(0.);
+        if grounded.0 {
+            dash.air_dashed = false;
+        }
+        if !input.just_pressed(PlayerInput::Dash) || dash.cooldown > 0. {
+            continue;
+        }
+        if !grounded.0 && (dash.air_dashed || !characters.abilities(*player).air_dash) {
+            continue;
+        }
+        dash.active = config.dash.duration;
+        dash.cooldown = config.dash.cooldown;
+        dash.immunity = config.dash.immunity;
+        dash.air_dashed |= !grounded.0;
+    }
+}
+
+/// ghosts get their `Dash` from the recording, so this drives their dashes too
+fn apply_dash(mut query: Query<(&Dash, &Facing, &mut Velocity)>, config: Res<MovementConfig>) {
+    for (dash, facing, mut velocity) in &mut query {
+        if !dash.is_dashing() {
+            continue;
+        }
+        velocity.linvel.x = facing.sign() * config.dash.speed;
+        if config.dash.suspend_gravity {
+            velocity.linvel.y = 0.;
         }
-        if input.just_pressed(PlayerInput::Jump) && jump.0 {
-            jump.0 = false;
-            velocity.linvel.y = 250.;
+    }
+}
+
+/// Seconds left of falling through one-way platforms
+#[derive(Component, Reflect, Default, Clone, Copy)]
+pub struct DropThrough(pub f32);
+
+impl DropThrough {
+    pub fn is_dropping(&self) -> bool {
+        self.0 > 0.
+    }
+}
+
+/// jumping while holding fall on a one-way platform drops through it instead
+fn drop_through(
+    mut player: Query<(
+        &mut DropThrough,
+        &mut JumpTimers,
+        &GroundContact,
+        &ActionState<PlayerInput>,
+    )>,
+    platforms: Query<(), With<OneWayPlatform>>,
+    config: Res<MovementConfig>,
+    time: Res<Time>,
+) {
+    for (mut drop, mut timers, contact, input) in &mut player {
+        drop.0 = (drop.0 - time.delta_seconds()).max(0.);
+        let on_platform = contact.entity.map_or(false, |e| platforms.contains(e));
+        if on_platform && input.pressed(PlayerInput::Fall) && timers.buffered(&config.jump) {
+            timers.consume();
+            drop.0 = config.jump.drop_through_time;
         }
     }
 }
 
-fn change_player(mut query: Query<(&mut Player, &ActionState<PlayerInput>)>) {
+/// letting go of jump while still rising cuts the jump down to the minimum height
+fn cut_jump(
+    mut player: Query<(&mut Velocity, &ActionState<PlayerInput>)>,
+    config: Res<MovementConfig>,
+) {
+    let min_velocity = JumpConfig::velocity(config.jump.min_height, config.gravity);
+    for (mut velocity, input) in &mut player {
+        if input.just_released(PlayerInput::Jump) && velocity.linvel.y > min_velocity {
+            velocity.linvel.y = min_velocity;
+        }
+    }
+}
+
+fn change_player(
+    mut query: Query<(&mut Player, &ActionState<PlayerInput>)>,
+    characters: Res<Characters>,
+    roster: Res<Roster>,
+) {
     for (mut player, state) in &mut query {
         if state.just_pressed(PlayerInput::NextPlayer) {
-            *player = match *player {
-                Player::Mask => Player::Ninja,
-                Player::Ninja => Player::Pink,
-                Player::Pink => Player::Guy,
-                Player::Guy => Player::Mask,
-            };
+            *player = roster.step(&characters, *player, true);
         } else if state.just_pressed(PlayerInput::PevPlayer) {
-            *player = match *player {
-                Player::Mask => Player::Ninja,
-                Player::Ninja => Player::Pink,
-                Player::Pink => Player::Guy,
-                Player::Guy => Player::Mask,
-            };
+            *player = roster.step(&characters, *player, false);
         }
     }
 }
@@ -176,25 +612,65 @@ pub struct Jump(pub bool);
 
 #[derive(Component, Reflect)]
 pub struct Grounded(pub bool);
+/// What the last ground check found under the entity
 #[derive(Component, Default)]
-pub struct GroundedCheck(f32, isize);
+pub struct GroundContact {
+    pub entity: Option<Entity>,
+    /// points away from the ground, `Vec2::ZERO` while in the air
+    pub normal: Vec2,
+}
 
-fn ground_detection(mut player: Query<(&Transform, &mut Grounded, &mut GroundedCheck)>) {
-    for (pos, mut on_ground, mut last) in &mut player {
-        if (pos.translation.y * 100.).round() == last.0 {
-            last.1 += 1;
-        } else {
-            last.1 -= 1;
-        };
-        last.1 = last.1.clamp(0, 5);
+/// how far below the feet the ground is still touching
+const GROUND_DISTANCE: f32 = 1.;
+/// steepest slope that can be stood on, as the `y` of its normal
+const MIN_GROUND_NORMAL_Y: f32 = 0.7;
 
-        if last.1 == 5 && !on_ground.0 {
-            on_ground.0 = true;
-        } else if last.1 < 2 && on_ground.0 {
-            on_ground.0 = false;
-        }
+/// casts a thin slice of the collider down to find what it is standing on
+fn ground_detection(
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &Collider,
+        &mut Grounded,
+        &mut GroundContact,
+        &Velocity,
+        Option<&DropThrough>,
+    )>,
+    platforms: Query<(), With<OneWayPlatform>>,
+    rapier_context: Res<RapierContext>,
+) {
+    for (entity, pos, collider, mut grounded, mut contact, velocity, drop) in &mut query {
+        let Some(cuboid) = collider.as_cuboid() else {continue;};
+        let half = cuboid.half_extents();
+        // a little narrower than the body so touching a wall is not standing on it
+        let probe = Collider::cuboid(half.x - 0.5, 0.5);
+        let start = pos.translation.truncate() - Vec2::Y * (half.y - 1.);
+        // jumping up through a platform or falling through it is not standing on it
+        let through_platforms =
+            velocity.linvel.y > 0. || drop.map_or(false, |drop| drop.is_dropping());
+        let solid = |hit: Entity| !(through_platforms && platforms.contains(hit));
+        let hit = rapier_context
+            .cast_shape(
+                start,
+                0.,
+                Vec2::NEG_Y,
+                &probe,
+                0.5 + GROUND_DISTANCE,
+                // the kinematic controller is not dynamic, so leave out the entity itself too
+                QueryFilter::exclude_dynamic()
+                    .exclude_sensors()
+                    .exclude_rigid_body(entity)
+                    .predicate(&solid),
+            )
+            .map(|(entity, toi)| (entity, -toi.normal2))
+            .filter(|(_, normal)| normal.y >= MIN_GROUND_NORMAL_Y);
 
-        last.0 = (pos.translation.y * 100.).round();
+        let on_ground = hit.is_some();
+        if grounded.0 != on_ground {
+            grounded.0 = on_ground;
+        }
+        contact.entity = hit.map(|(entity, _)| entity);
+        contact.normal = hit.map_or(Vec2::ZERO, |(_, normal)| normal);
     }
 }
 
@@ -212,16 +688,21 @@ impl std::ops::BitAnd<&Grounded> for bool {
     }
 }
 
+/// the kinematic controller steps up on its own
 fn auto_step(
-    mut query: Query<(&mut Transform, &ActionState<PlayerInput>, &Grounded)>,
+    mut query: Query<
+        (&mut Transform, &ActionState<PlayerInput>, &Grounded),
+        Without<KinematicCharacterController>,
+    >,
     rapier_context: Res<RapierContext>,
+    config: Res<MovementConfig>,
 ) {
     for (mut offset, state, grounded) in &mut query {
         if state.pressed(PlayerInput::Left) {
             let step = rapier_context.cast_ray(
                 offset.translation.truncate() + Vec2::new(-10., 0.01),
                 Vec2::NEG_Y,
-                15.9,
+                config.step_height,
                 true,
                 QueryFilter::exclude_dynamic().exclude_sensors(),
             );
@@ -251,7 +732,7 @@ fn auto_step(
             let step = rapier_context.cast_ray(
                 offset.translation.truncate() + Vec2::new(10., 0.01),
                 Vec2::NEG_Y,
-                15.9,
+                config.step_height,
                 true,
                 QueryFilter::exclude_dynamic().exclude_sensors(),
             );
@@ -0,0 +1,216 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use bevy::{
+    asset::FileAssetIo,
+    prelude::{
+        error, info, App, Changed, EventReader, IntoSystemAppConfig, OnEnter, Plugin, Query, Res,
+        ResMut, Resource,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    characters::{Character, Characters, Unlock},
+    player::{Player, RealPlayer},
+    GameState, Score,
+};
+
+const ROSTER_PATH: &str = "roster.ron";
+
+pub struct RosterPlugin;
+
+impl Plugin for RosterPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Roster::load())
+            .add_event::<LevelCompleted>()
+            .add_system(unlock_characters)
+            .add_system(complete_levels)
+            .add_system(apply_selected_character.in_schedule(OnEnter(GameState::Play)));
+    }
+}
+
+/// Sent with the asset path of a level when a real player reaches its finish
+pub struct LevelCompleted(pub String);
+
+/// The characters unlocked so far and the one to start with, kept in `roster.ron`
+#[derive(Resource, Debug, Default, Deserialize, Serialize)]
+pub struct Roster {
+    /// names of the characters that were unlocked
+    pub unlocked: HashSet<String>,
+    pub best_score: usize,
+    /// asset paths of the levels that were finished
+    #[serde(default)]
+    pub completed_levels: HashSet<String>,
+    /// name of the character picked on the select screen
+    pub selected: Option<String>,
+}
+
+impl Roster {
+    fn path() -> PathBuf {
+        FileAssetIo::get_base_path().join(ROSTER_PATH)
+    }
+
+    pub fn load() -> Roster {
+        let path = Roster::path();
+        let Ok(bytes) = std::fs::read(&path) else {return Roster::default();};
+        match ron::de::from_bytes(&bytes) {
+            Ok(roster) => roster,
+            Err(e) => {
+                error!("Failed to parse {:?}: {}", path, e);
+                Roster::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = Roster::path();
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(anyhow::Error::from)
+            .and_then(|text| std::fs::write(&path, text).map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            error!("Failed to save {:?}: {}", path, e);
+        }
+    }
+
+    pub fn is_unlocked(&self, character: &Character) -> bool {
+        match &character.unlock {
+            Unlock::Always => true,
+            Unlock::Score(score) => {
+                self.best_score >= *score || self.unlocked.contains(&character.name)
+            }
+            Unlock::Level(level) => {
+                self.completed_levels.contains(level) || self.unlocked.contains(&character.name)
+            }
+        }
+    }
+
+    /// records `score` and returns the names of the characters it unlocked
+    pub fn add_score(&mut self, characters: &Characters, score: usize) -> Vec<String> {
+        if score <= self.best_score {
+            return Vec::new();
+        }
+        self.best_score = score;
+        self.unlock_new(characters)
+    }
+
+    /// records that `level` was finished and returns the names of the characters it unlocked
+    pub fn complete_level(&mut self, characters: &Characters, level: &str) -> Vec<String> {
+        if !self.completed_levels.insert(level.to_string()) {
+            return Vec::new();
+        }
+        self.unlock_new(characters)
+    }
+
+    fn unlock_new(&mut self, characters: &Characters) -> Vec<String> {
+        let mut unlocked = Vec::new();
+        for (_, character) in characters.iter() {
+            if self.is_unlocked(character) && self.unlocked.insert(character.name.clone()) {
+                unlocked.push(character.name.clone());
+            }
+        }
+        unlocked
+    }
+
+    /// the next unlocked character after `player`, going backwards when `forward` is false
+    pub fn step(&self, characters: &Characters, player: Player, forward: bool) -> Player {
+        let mut next = player;
+        for _ in 0..characters.len() {
+            next = if forward {
+                characters.next(next)
+            } else {
+                characters.prev(next)
+            };
+            if characters.get(next).map_or(false, |c| self.is_unlocked(c)) {
+                return next;
+            }
+        }
+        player
+    }
+
+    /// the selected character if it is still in the manifest and unlocked
+    pub fn selected(&self, characters: &Characters) -> Option<Player> {
+        let name = self.selected.as_ref()?;
+        characters
+            .iter()
+            .find(|(_, c)| c.name == *name && self.is_unlocked(c))
+            .map(|(player, _)| player)
+    }
+
+    /// the selected character for the first player, each next slot gets the next unlocked one
+    pub fn starting_character(&self, characters: &Characters, slot: usize) -> Player {
+        let mut player = self.selected(characters).unwrap_or_default();
+        for _ in 0..slot {
+            player = self.step(characters, player, true);
+        }
+        player
+    }
+}
+
+fn unlock_characters(
+    scores: Query<&Score, Changed<Score>>,
+    mut roster: ResMut<Roster>,
+    characters: Res<Characters>,
+) {
+    let Some(score) = scores.iter().map(|score| score.0).max() else {return;};
+    if score <= roster.best_score {
+        return;
+    }
+    for name in roster.add_score(&characters, score) {
+        info!("Unlocked {}", name);
+    }
+    roster.save();
+}
+
+fn complete_levels(
+    mut events: EventReader<LevelCompleted>,
+    mut roster: ResMut<Roster>,
+    characters: Res<Characters>,
+) {
+    let mut changed = false;
+    for LevelCompleted(level) in events.iter() {
+        changed |= !roster.completed_levels.contains(level);
+        for name in roster.complete_level(&characters, level) {
+            info!("Unlocked {}", name);
+        }
+    }
+    if changed {
+        roster.save();
+    }
+}
+
+fn apply_selected_character(
+    mut players: Query<(&mut Player, &RealPlayer)>,
+    roster: Res<Roster>,
+    characters: Res<Characters>,
+) {
+    if roster.selected(&characters).is_none() {
+        return;
+    }
+    for (mut player, &RealPlayer(slot)) in &mut players {
+        *player = roster.starting_character(&characters, slot);
+    }
+}
+
+#[test]
+fn level_unlock() {
+    let character = Character {
+        name: "Frog".to_string(),
+        animations: String::new(),
+        states: Default::default(),
+        abilities: Default::default(),
+        unlock: Unlock::Level("Levels/test.lvl.ron".to_string()),
+    };
+    let mut roster = Roster {
+        best_score: 100,
+        ..Default::default()
+    };
+    assert!(!roster.is_unlocked(&character));
+    roster
+        .completed_levels
+        .insert("Levels/other.lvl.ron".to_string());
+    assert!(!roster.is_unlocked(&character));
+    roster
+        .completed_levels
+        .insert("Levels/test.lvl.ron".to_string());
+    assert!(roster.is_unlocked(&character));
+}
@@ -1,12 +1,19 @@
-use bevy::prelude::KeyCode;
-use leafwing_input_manager::{prelude::InputMap, Actionlike};
+use bevy::prelude::{Gamepad, GamepadAxisType, GamepadButtonType, KeyCode};
+use leafwing_input_manager::{
+    prelude::{InputMap, SingleAxis},
+    Actionlike,
+};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Actionlike, Clone)]
+#[derive(
+    Debug, Actionlike, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize,
+)]
 pub enum PlayerInput {
     Left,
     Right,
     Jump,
     Fall,
+    Dash,
     NextPlayer,
     PevPlayer,
 }
@@ -24,9 +31,51 @@ impl PlayerInput {
             (KeyCode::Up, PlayerInput::Jump),
             (KeyCode::S, PlayerInput::Fall),
             (KeyCode::Down, PlayerInput::Fall),
+            (KeyCode::LShift, PlayerInput::Dash),
+            (KeyCode::RShift, PlayerInput::Dash),
             (KeyCode::Q, PlayerInput::PevPlayer),
             (KeyCode::E, PlayerInput::NextPlayer),
         ]);
+        PlayerInput::insert_gamepad(&mut map);
+        map.set_gamepad(Gamepad::new(0));
         map
     }
+    /// the second gamepad, the first one is shared with the keyboard by player one
+    pub fn player_two() -> InputMap<PlayerInput> {
+        PlayerInput::gamepad(Gamepad::new(1))
+    }
+    /// the default input map of the real player in `slot`, slot `n` gets gamepad `n`
+    pub fn for_slot(slot: usize) -> InputMap<PlayerInput> {
+        match slot {
+            0 => PlayerInput::player_one(),
+            1 => PlayerInput::player_two(),
+            n => PlayerInput::gamepad(Gamepad::new(n)),
+        }
+    }
+    fn gamepad(gamepad: Gamepad) -> InputMap<PlayerInput> {
+        let mut map = InputMap::default();
+        PlayerInput::insert_gamepad(&mut map);
+        map.set_gamepad(gamepad);
+        map
+    }
+    /// a binding for every action on a standard gamepad
+    fn insert_gamepad(map: &mut InputMap<PlayerInput>) {
+        map.insert_multiple([
+            (GamepadButtonType::DPadLeft, PlayerInput::Left),
+            (GamepadButtonType::DPadRight, PlayerInput::Right),
+            (GamepadButtonType::South, PlayerInput::Jump),
+            (GamepadButtonType::DPadDown, PlayerInput::Fall),
+            (GamepadButtonType::West, PlayerInput::Dash),
+            (GamepadButtonType::LeftTrigger, PlayerInput::PevPlayer),
+            (GamepadButtonType::RightTrigger, PlayerInput::NextPlayer),
+        ]);
+        map.insert(
+            SingleAxis::negative_only(GamepadAxisType::LeftStickX, -0.5),
+            PlayerInput::Left,
+        );
+        map.insert(
+            SingleAxis::positive_only(GamepadAxisType::LeftStickX, 0.5),
+            PlayerInput::Right,
+        );
+    }
 }